@@ -0,0 +1,156 @@
+use crate::*;
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{HashMap, HashSet},
+};
+use serde::Deserialize;
+
+pub const BINDINGS_FILE: &str = "input.bindings.ron";
+
+/// How far a stick has to be pushed before an axis binding counts as held.
+pub const AXIS_THRESHOLD: f32 = 0.5;
+
+/// Something the player can ask for, whatever key or button it is bound to.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    /// Turn the actor counter-clockwise.
+    TurnLeft,
+    /// Turn the actor clockwise.
+    TurnRight,
+    Fire,
+    /// Take up the next weapon the level allows.
+    SwitchWeapon,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::Fire,
+        Action::SwitchWeapon,
+    ];
+}
+
+/// A key or gamepad input that triggers an action.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A button on any connected gamepad.
+    Button(GamepadButtonType),
+    /// A stick or trigger on any connected gamepad, held while it is pushed
+    /// past `AXIS_THRESHOLD` in the given direction, `1.` or `-1.`.
+    Axis(GamepadAxisType, f32),
+}
+
+/// Which inputs trigger each action, as read from the bindings file.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "9a1d7c3e-48b2-4f06-b5e8-2d6f0c81a4b7"]
+pub struct ActionBindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionBindings {
+    /// The bindings the game ships with: arrow keys and space, or the d-pad
+    /// or left stick to move, the shoulder buttons or right stick to turn and
+    /// the south button to fire and north to switch weapons.
+    fn default() -> Self {
+        Self::from_ron(include_str!("../assets/input.bindings.ron")).unwrap()
+    }
+}
+
+impl RonAsset for ActionBindings {
+    const EXTENSIONS: &'static [&'static str] = &["bindings.ron"];
+
+    fn from_ron(source: &str) -> Result<Self, DataError> {
+        Ok(ron::from_str(source)?)
+    }
+}
+
+impl ActionBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+}
+
+pub struct BindingsHandle(pub Handle<ActionBindings>);
+
+/// The actions held down this frame, and which of them were only just pressed.
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// Turns keyboard and gamepad input into actions, so gameplay never has to
+/// know what the player has bound them to. Without Bevy's `InputPlugin` the
+/// keyboard and gamepad resources start out empty and nothing is held.
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .add_asset::<ActionBindings>()
+            .init_asset_loader::<RonLoader<ActionBindings>>()
+            .init_resource::<ActionState>()
+            .add_startup_system(bindings_setup_system)
+            .add_system(action_input_system);
+    }
+}
+
+pub fn bindings_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BindingsHandle(asset_server.load(BINDINGS_FILE)));
+}
+
+/// Works out which actions are held from the bindings file, or the default
+/// bindings until it has loaded.
+#[allow(clippy::too_many_arguments)]
+pub fn action_input_system(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    bindings_assets: Res<Assets<ActionBindings>>,
+    bindings_handle: Option<Res<BindingsHandle>>,
+    default_bindings: Local<ActionBindings>,
+    mut actions: ResMut<ActionState>,
+) {
+    let bindings = bindings_handle
+        .and_then(|handle| bindings_assets.get(&handle.0))
+        .unwrap_or(&default_bindings);
+
+    let held = |binding: &Binding| match *binding {
+        Binding::Key(key) => keys.pressed(key),
+        Binding::Button(button) => gamepads
+            .iter()
+            .any(|gamepad| buttons.pressed(GamepadButton(*gamepad, button))),
+        Binding::Axis(axis, direction) => gamepads.iter().any(|gamepad| {
+            axes.get(GamepadAxis(*gamepad, axis))
+                .is_some_and(|value| value * direction > AXIS_THRESHOLD)
+        }),
+    };
+
+    let pressed: HashSet<Action> = Action::ALL
+        .into_iter()
+        .filter(|action| bindings.get(*action).iter().any(held))
+        .collect();
+    actions.just_pressed = pressed.difference(&actions.pressed).copied().collect();
+    actions.pressed = pressed;
+}
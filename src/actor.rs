@@ -1,82 +1,254 @@
 use crate::*;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub const ACTOR_SPRITE_SIZE: (f32, f32) = (70.0, 70.0);
 pub const ACTOR_SPRITE: &str = "laserUp.png";
 pub const ACTOR_SCALE: f32 = 1.0;
-pub const ACTOR_SPRITE_OFFSET: f32 = ACTOR_SPRITE_SIZE.0 / 2.0;
 
-pub const ACTOR_ANIMATE_SPRITE: &str = "laserRightFire-sprites.png";
-pub const ACTOR_ANIMATE_SPRITE_ROWS: usize = 4;
-pub const ACTOR_ANIMATE_SPRITE_COLS: usize = 4;
+/// Clip played over the actor every time it fires.
+pub const ACTOR_FIRE_CLIP: &str = "actor_fire";
 
-pub fn actor_move_system(mut query: Query<(&Velocity, &mut Transform), With<Actor>>) {
-    for (velocity, mut transform) in query.iter_mut() {
-        let translation = &mut transform.translation;
-        translation.x += velocity.x;
-        translation.y += velocity.y * 10.0;
+/// How fast the actor moves up and down, in pixels per second.
+pub const ACTOR_SPEED: f32 = 600.;
+
+/// How fast the actor turns while a turn action is held, in radians per second.
+pub const ACTOR_TURN_SPEED: f32 = std::f32::consts::PI;
+
+/// What the player asked the actor to do. Sampled from the actions every
+/// frame and read by the next tick; a shot or a cursor aim stays queued until
+/// a tick uses it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct ActorInput {
+    pub up: bool,
+    pub down: bool,
+    pub turn_left: bool,
+    pub turn_right: bool,
+    /// Angle to point the actor at, from the mouse cursor.
+    pub aim: Option<f32>,
+    pub fire: bool,
+    /// Fire is held down, which keeps a beam or rapid fire going and charges
+    /// a charge weapon.
+    pub firing: bool,
+    /// Take up the next weapon; queued like `fire`.
+    pub switch_weapon: bool,
+}
+
+/// Which way the actor points, in radians counter-clockwise from the right.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Aim(pub f32);
+
+impl Aim {
+    pub fn direction(self) -> Vec2 {
+        Vec2::new(self.0.cos(), self.0.sin())
+    }
+
+    /// The actor's sprite points up, so it is turned a quarter less than the aim.
+    pub fn rotation(self) -> Quat {
+        Quat::from_rotation_z(self.0 - std::f32::consts::FRAC_PI_2)
+    }
+}
+
+/// The player's laser emitter: spawning it, steering it and firing.
+pub(crate) struct ActorPlugin;
+
+impl Plugin for ActorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActorInput>()
+            .add_system(actor_action_system.after(action_input_system))
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::GameSetup)
+                    .label(TickStep::Actor)
+                    .after(TickStep::Input)
+                    .with_system(actor_setup_system),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_update(GameState::Running)
+                    .label(TickStep::Actor)
+                    .after(TickStep::Input)
+                    .with_system(actor_steer_system)
+                    .with_system(actor_aim_system.after(actor_steer_system))
+                    .with_system(actor_move_system.after(actor_aim_system))
+                    .with_system(actor_weapon_switch_system.after(actor_move_system))
+                    .with_system(actor_weapon_fire_system.after(actor_weapon_switch_system)),
+            );
+    }
+}
+
+type ActorObstacles<'w, 's> =
+    Query<'w, 's, (&'static Transform, &'static SpriteSize), (With<Block>, Without<Actor>)>;
+
+/// The actor's hitbox at `position` overlaps a block.
+fn actor_blocked(position: Vec2, block_grid: &BlockGrid, block_query: &ActorObstacles) -> bool {
+    let size = Vec2::splat(ACTOR_HITBOX_SIZE);
+    block_grid
+        .candidates(position.extend(0.), size)
+        .into_iter()
+        .filter_map(|entity| block_query.get(entity).ok())
+        .any(|(transform, sprite_size)| {
+            let gap =
+                (position - transform.translation.truncate()).abs() - (size + sprite_size.0) / 2.;
+            gap.x < -TOUCH_TOLERANCE && gap.y < -TOUCH_TOLERANCE
+        })
+}
+
+/// Moves the actor along its lane, up towards the lane's first point and
+/// down towards its last, stopping where it would run into a block.
+pub fn actor_move_system(
+    clock: Res<GameClock>,
+    level: CurrentLevel,
+    win_size: Res<WinSize>,
+    block_grid: Res<BlockGrid>,
+    block_query: ActorObstacles,
+    mut query: Query<(&Velocity, &mut OnLane, &mut Transform), With<Actor>>,
+) {
+    let level = match level.get() {
+        Some(level) => level,
+        None => return,
+    };
+    let lanes = level_lanes(level, &win_size);
+    let delta = clock.delta_seconds();
+
+    for (velocity, mut on_lane, mut transform) in query.iter_mut() {
+        // the level was edited and the lane went away
+        if on_lane.lane >= lanes.len() {
+            *on_lane = OnLane::closest(&lanes, transform.translation.truncate());
+        }
+
+        // a pixel at a time, so the actor stops right against a block
+        let travel = -velocity.y * delta;
+        let steps = travel.abs().ceil().max(1.);
+        for _ in 0..steps as usize {
+            let next = on_lane.advance(&lanes, travel / steps);
+            let position = lanes[next.lane].point_at(next.distance);
+            if actor_blocked(position, &block_grid, &block_query) {
+                break;
+            }
+            *on_lane = next;
+        }
+
+        let position = lanes[on_lane.lane].point_at(on_lane.distance);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
     }
 }
 
-pub fn actor_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let (x, y) = (
-        -SCREEN_WIDTH_OFFSET + ACTOR_SPRITE_OFFSET,
-        -SCREEN_HEIGHT_OFFSET + ACTOR_SPRITE_OFFSET,
-    );
-    // let (x,y) = (0.0,1.0);
+pub fn actor_setup_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<Level>>,
+    level_handle: Res<LevelHandle>,
+    weapons: Weapons,
+    win_size: Res<WinSize>,
+) {
+    let level = levels.get(&level_handle.0).unwrap();
+    let arsenal = weapons.get().unwrap();
+    let available = if level.weapons.is_empty() {
+        arsenal.iter().cloned().collect()
+    } else {
+        level
+            .weapons
+            .iter()
+            .filter_map(|name| arsenal.get(name).cloned())
+            .collect()
+    };
+
+    let (x, y) = level.actor_start;
+    let lanes = level_lanes(level, &win_size);
+    let on_lane = OnLane::closest(&lanes, level.grid_to_world(x, y));
+    let start = lanes[on_lane.lane].point_at(on_lane.distance);
 
     commands
         .spawn_bundle(SpriteBundle {
             texture: asset_server.load(ACTOR_SPRITE),
             transform: Transform {
                 scale: Vec3::new(ACTOR_SCALE, ACTOR_SCALE, 1.),
-                translation: Vec3::new(x, y, 2.0),
-                rotation: Quat::from_rotation_z(4.71_f32), // rads is 270 degrees counter-clockwise
+                translation: start.extend(2.0),
+                rotation: Aim::default().rotation(),
             },
             ..Default::default()
         })
         .insert(Actor)
-        .insert(Velocity { x: 0.0, y: 1.0 });
+        .insert(Aim::default())
+        .insert(Weapon::new(available))
+        .insert(on_lane)
+        .insert(Velocity { x: 0.0, y: 0.0 })
+        .insert(LevelEntity);
 }
 
-pub fn actor_keyboard_event_system(
-    kb: Res<Input<KeyCode>>,
-    mut query: Query<&mut Velocity, With<Actor>>,
+/// Samples the actions, and the mouse cursor whenever it moves, into the
+/// actor's input.
+pub fn actor_action_system(
+    actions: Res<ActionState>,
+    player: Option<Res<ReplayPlayer>>,
+    windows: Option<Res<Windows>>,
+    mut last_cursor: Local<Option<Vec2>>,
+    query: Query<&Transform, With<Actor>>,
+    mut input: ResMut<ActorInput>,
 ) {
+    // a replay drives the actor instead
+    if player.is_some() {
+        return;
+    }
+    input.up = actions.pressed(Action::MoveUp);
+    input.down = actions.pressed(Action::MoveDown);
+    input.turn_left = actions.pressed(Action::TurnLeft);
+    input.turn_right = actions.pressed(Action::TurnRight);
+    input.fire |= actions.just_pressed(Action::Fire);
+    input.firing = actions.pressed(Action::Fire);
+    input.switch_weapon |= actions.just_pressed(Action::SwitchWeapon);
+
+    // the camera sits at the origin, so the window centre is the world origin
+    let window = match windows.as_ref().and_then(|windows| windows.get_primary()) {
+        Some(window) => window,
+        None => return,
+    };
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor - Vec2::new(window.width(), window.height()) / 2.,
+        None => return,
+    };
+    if *last_cursor != Some(cursor) {
+        *last_cursor = Some(cursor);
+        if let Ok(transform) = query.get_single() {
+            let offset = cursor - transform.translation.truncate();
+            input.aim = Some(offset.y.atan2(offset.x));
+        }
+    }
+}
+
+pub fn actor_steer_system(input: Res<ActorInput>, mut query: Query<&mut Velocity, With<Actor>>) {
     if let Ok(mut velocity) = query.get_single_mut() {
-        velocity.y = if kb.pressed(KeyCode::Down) {
-            -1.
-        } else if kb.pressed(KeyCode::Up) {
-            1.
+        velocity.y = if input.down {
+            -ACTOR_SPEED
+        } else if input.up {
+            ACTOR_SPEED
         } else {
             0.
         }
     }
 }
 
-pub fn actor_laser_spawn_system(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    kb: Res<Input<KeyCode>>,
-    query: Query<&Transform, With<Actor>>,
+/// Turns the actor while a turn action is held, or points it straight at the
+/// cursor.
+pub fn actor_aim_system(
+    clock: Res<GameClock>,
+    mut input: ResMut<ActorInput>,
+    mut query: Query<(&mut Aim, &mut Transform), With<Actor>>,
 ) {
-    if let Ok(player_tf) = query.get_single() {
-        if kb.just_pressed(KeyCode::Space) {
-            let (x, y) = (player_tf.translation.x, player_tf.translation.y);
-
-            commands
-                .spawn_bundle(SpriteBundle {
-                    texture: asset_server.load(LASER_SPRITE),
-                    transform: Transform {
-                        scale: Vec3::new(LASER_SCALE, LASER_SCALE, 1.),
-                        translation: Vec3::new(x + ACTOR_SPRITE_SIZE.0, y, 2.0),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(Velocity { x: 5.0, y: 0.0 })
-                .insert(SpriteSize::from((70.0, 8.0)))
-                .insert(Laser);
+    if let Ok((mut aim, mut transform)) = query.get_single_mut() {
+        if let Some(angle) = input.aim.take() {
+            aim.0 = angle;
+        }
+        if input.turn_left {
+            aim.0 += ACTOR_TURN_SPEED * clock.delta_seconds();
+        }
+        if input.turn_right {
+            aim.0 -= ACTOR_TURN_SPEED * clock.delta_seconds();
         }
+        aim.0 = aim.0.rem_euclid(std::f32::consts::TAU);
+        transform.rotation = aim.rotation();
     }
 }
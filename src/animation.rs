@@ -0,0 +1,333 @@
+use crate::*;
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{HashMap, HashSet},
+};
+use serde::Deserialize;
+
+pub const ANIMATIONS_FILE: &str = "effects.animations.ron";
+
+/// What a clip does after its last frame.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnimationMode {
+    /// Starts again from the first frame.
+    Loop,
+    /// Stays on the last frame and sends `AnimationFinished`.
+    Once,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong,
+}
+
+/// One animation, as described in the animations file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Sprite sheet the frames are cut from, in `rows` of `columns` tiles of
+    /// `tile_size` pixels.
+    pub sheet: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    /// First and last tile of the clip, counting along the rows.
+    pub frames: (usize, usize),
+    pub fps: f32,
+    pub mode: AnimationMode,
+}
+
+impl AnimationClip {
+    pub fn frame_count(&self) -> usize {
+        self.frames.1 - self.frames.0 + 1
+    }
+}
+
+#[derive(Deserialize)]
+struct AnimationsFile {
+    clips: Vec<AnimationClip>,
+}
+
+/// Every animation clip, looked up by name.
+#[derive(TypeUuid)]
+#[uuid = "b7f41c08-2e6d-4a93-9c5b-0d8e3f6a127c"]
+pub struct AnimationLibrary {
+    clips: Vec<AnimationClip>,
+}
+
+impl RonAsset for AnimationLibrary {
+    const EXTENSIONS: &'static [&'static str] = &["animations.ron"];
+
+    fn from_ron(source: &str) -> Result<Self, DataError> {
+        let file: AnimationsFile = ron::from_str(source)?;
+
+        let mut names = HashSet::new();
+        for clip in file.clips.iter() {
+            let (first, last) = clip.frames;
+            let defined_before = !names.insert(clip.name.clone());
+            let problem = if defined_before {
+                Some("is defined twice".to_string())
+            } else if first > last || last >= clip.columns * clip.rows {
+                Some(format!(
+                    "frames {:?} are not on its {}x{} sheet",
+                    clip.frames, clip.columns, clip.rows
+                ))
+            } else if clip.fps <= 0. {
+                Some("needs a positive fps".to_string())
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err(DataError::at_name(
+                    source,
+                    &clip.name,
+                    defined_before as usize,
+                    format!("clip \"{}\" {}", clip.name, problem),
+                ));
+            }
+        }
+
+        Ok(AnimationLibrary { clips: file.clips })
+    }
+}
+
+impl AnimationLibrary {
+    pub fn get(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+}
+
+pub struct AnimationsHandle(pub Handle<AnimationLibrary>);
+
+impl AssetHandle for AnimationsHandle {
+    type Asset = AnimationLibrary;
+
+    fn handle(&self) -> &Handle<AnimationLibrary> {
+        &self.0
+    }
+}
+
+/// The loaded animation library, for systems that only need to read it.
+pub type Animations<'w, 's> = Loaded<'w, 's, AnimationsHandle>;
+
+/// The texture atlas of each clip, cut from its sheet once the library has
+/// loaded. Empty without the sprite plugin.
+#[derive(Default)]
+pub struct AnimationAtlases(HashMap<String, Handle<TextureAtlas>>);
+
+impl AnimationAtlases {
+    pub fn get(&self, clip: &str) -> Handle<TextureAtlas> {
+        self.0.get(clip).cloned().unwrap_or_default()
+    }
+}
+
+/// Plays a clip from the animation library on an entity's atlas sprite.
+#[derive(Component, Clone, Debug)]
+pub struct SpriteAnimation {
+    pub clip: String,
+    /// Frame being shown, counted from the first frame of the clip.
+    pub frame: usize,
+    /// Seconds the frame has been shown for.
+    elapsed: f32,
+    /// Heading back to the first frame of a ping-pong.
+    reversed: bool,
+    pub finished: bool,
+}
+
+impl SpriteAnimation {
+    pub fn new(clip: &str) -> Self {
+        SpriteAnimation {
+            clip: clip.to_string(),
+            frame: 0,
+            elapsed: 0.,
+            reversed: false,
+            finished: false,
+        }
+    }
+
+    /// Moves on a frame the way the clip's mode says.
+    fn advance(&mut self, clip: &AnimationClip) {
+        let last = clip.frame_count() - 1;
+        match clip.mode {
+            AnimationMode::Loop => self.frame = if self.frame < last { self.frame + 1 } else { 0 },
+            AnimationMode::Once if self.frame < last => self.frame += 1,
+            AnimationMode::Once => self.finished = true,
+            AnimationMode::PingPong if last == 0 => {}
+            AnimationMode::PingPong => {
+                if self.frame == last {
+                    self.reversed = true;
+                } else if self.frame == 0 {
+                    self.reversed = false;
+                }
+                if self.reversed {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Sent when a clip played `Once` is over.
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: String,
+}
+
+/// An animation that only plays once and is removed when its clip finishes,
+/// like an explosion or a muzzle flash.
+#[derive(Component)]
+pub struct AnimatedEffect;
+
+/// Spawns a one-off animation of `clip`.
+pub fn spawn_effect(
+    commands: &mut Commands,
+    atlases: &AnimationAtlases,
+    clip: &str,
+    transform: Transform,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: atlases.get(clip),
+            transform,
+            ..Default::default()
+        })
+        .insert(SpriteAnimation::new(clip))
+        .insert(AnimatedEffect)
+        .insert(LevelEntity)
+        .id()
+}
+
+/// Plays sprite animations on the game tick, so they keep time with the
+/// rest of the game.
+pub(crate) struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationLibrary>()
+            .init_asset_loader::<RonLoader<AnimationLibrary>>()
+            .init_resource::<AnimationAtlases>()
+            .add_event::<AnimationFinished>()
+            .add_system(animation_atlas_system)
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::StartUp)
+                    .label(TickStep::Animation)
+                    .after(TickStep::Explosions)
+                    .with_system(animation_setup_system),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_update(GameState::Running)
+                    .label(TickStep::Animation)
+                    .after(TickStep::Explosions)
+                    .with_system(sprite_animation_system)
+                    .with_system(animated_effect_system.after(sprite_animation_system)),
+            );
+    }
+}
+
+pub fn animation_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AnimationsHandle(asset_server.load(ANIMATIONS_FILE)));
+}
+
+/// Cuts every clip's sheet into a texture atlas whenever the library loads
+/// or changes on disk.
+pub fn animation_atlas_system(
+    mut events: EventReader<AssetEvent<AnimationLibrary>>,
+    asset_server: Res<AssetServer>,
+    libraries: Res<Assets<AnimationLibrary>>,
+    animations_handle: Option<Res<AnimationsHandle>>,
+    texture_atlases: Option<ResMut<Assets<TextureAtlas>>>,
+    mut atlases: ResMut<AnimationAtlases>,
+) {
+    let changed = events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => animations_handle
+            .as_ref()
+            .is_some_and(|animations_handle| *handle == animations_handle.0),
+        AssetEvent::Removed { .. } => false,
+    });
+    let library = match animations_handle.and_then(|handle| libraries.get(&handle.0)) {
+        Some(library) if changed => library,
+        _ => return,
+    };
+    // no sprite plugin, so nothing to draw them with
+    let mut texture_atlases = match texture_atlases {
+        Some(texture_atlases) => texture_atlases,
+        None => return,
+    };
+
+    atlases.0 = library
+        .clips
+        .iter()
+        .map(|clip| {
+            let atlas = TextureAtlas::from_grid(
+                asset_server.load(clip.sheet.as_str()),
+                Vec2::from(clip.tile_size),
+                clip.columns,
+                clip.rows,
+            );
+            (clip.name.clone(), texture_atlases.add(atlas))
+        })
+        .collect();
+}
+
+type AnimatedSprites<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut SpriteAnimation,
+        Option<&'static mut TextureAtlasSprite>,
+    ),
+>;
+
+/// Steps every animation by the tick's time and shows its frame.
+pub fn sprite_animation_system(
+    clock: Res<GameClock>,
+    animations: Animations,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut query: AnimatedSprites,
+) {
+    let library = match animations.get() {
+        Some(library) => library,
+        None => return,
+    };
+
+    for (entity, mut animation, sprite) in query.iter_mut() {
+        let clip = match library.get(&animation.clip) {
+            Some(clip) => clip,
+            None => continue,
+        };
+        if animation.finished {
+            continue;
+        }
+
+        animation.elapsed += clock.delta_seconds();
+        let frame_time = 1. / clip.fps;
+        while animation.elapsed >= frame_time && !animation.finished {
+            animation.elapsed -= frame_time;
+            animation.advance(clip);
+        }
+        if animation.finished {
+            finished_events.send(AnimationFinished {
+                entity,
+                clip: animation.clip.clone(),
+            });
+        }
+        if let Some(mut sprite) = sprite {
+            sprite.index = clip.frames.0 + animation.frame;
+        }
+    }
+}
+
+pub fn animated_effect_system(
+    mut commands: Commands,
+    mut finished_events: EventReader<AnimationFinished>,
+    query: Query<(), With<AnimatedEffect>>,
+) {
+    for finished in finished_events.iter() {
+        if query.get(finished.entity).is_ok() {
+            commands.entity(finished.entity).despawn();
+        }
+    }
+}
@@ -1,142 +1,239 @@
 use crate::*;
 use bevy::prelude::*;
 
-pub const BLOCK_LARGE_SPRITE_SIZE: (f32, f32) = (64.0, 64.0);
-pub const BLOCK_LARGE_SPRITE: &str = "base64/metalCenterSticker-64.png";
-pub const BLOCK_LARGE_SPRITE_OFFSET: f32 = BLOCK_LARGE_SPRITE_SIZE.0 / 2.0;
-
-pub const BLOCK_SUPPORT_SPRITE_SIZE: (f32, f32) = (64.0, 64.0);
-pub const BLOCK_SUPPORT_SPRITE: &str = "base64/beamBoltsHoles-64.png";
-pub const BLOCK_SUPPORT_SPRITE_OFFSET: f32 = BLOCK_LARGE_SPRITE_SIZE.0 / 2.0;
-
-pub const BLOCK_MEDIUM_SPRITE_SIZE: (f32, f32) = (16.0, 16.0);
 pub const BLOCK_MEDIUM_SIZE: f32 = 16.;
-pub const BLOCK_MEDIUM_SPRITE: &str = "base64/metalCenterWarning-16.png";
-pub const _BLOCK_MEDIUM_SCALE: f32 = 1.0;
-pub const _BLOCK_MEDIUM_SPRITE_OFFSET: f32 = BLOCK_LARGE_SPRITE_SIZE.0 / 2.0;
 
-pub const GRID_WIDTH: f32 = 64.;
-const NUMBER_COLS: usize = 16;
+pub const BLOCK_SMALL_SIZE: f32 = 4.;
 
-pub fn block_large_setup_system(
-    mut commands: Commands,
-    game_textures: Res<GameTextures>,
-    block_map: Res<BlockMap>,
-) {
-    let blocks = get_blocks_from_map('#', block_map);
+pub const GRID_WIDTH: f32 = 64.;
 
-    for (x, y) in blocks {
-        let (screen_x, screen_y) = (
-            x as f32 * GRID_WIDTH - SCREEN_WIDTH / 2. + BLOCK_LARGE_SPRITE_OFFSET,
-            -(y as f32 * GRID_WIDTH - SCREEN_HEIGHT / 2. + BLOCK_LARGE_SPRITE_OFFSET),
-        );
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: game_textures.block_large.clone(),
-                transform: Transform {
-                    translation: Vec3::new(screen_x, screen_y, 2.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(SpriteSize::from(BLOCK_LARGE_SPRITE_SIZE))
-            .insert(Block)
-            .insert(BlockHeat::new())
-            .insert(BlockSize::Large(64));
+/// Blocks and their materials: spawning them from the level, heat, melting,
+/// decimation, support and falling.
+pub(crate) struct BlockPlugin;
+
+impl Plugin for BlockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MaterialRegistry>()
+            .init_asset_loader::<RonLoader<MaterialRegistry>>()
+            .init_resource::<BlockGrid>()
+            .init_resource::<FallingConfig>()
+            .add_event::<BlockLanded>()
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::StartUp)
+                    .label(TickStep::Blocks)
+                    .after(TickStep::Lasers)
+                    .with_system(material_setup_system),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::GameSetup)
+                    .label(TickStep::Blocks)
+                    .after(TickStep::Lasers)
+                    .with_system(block_setup_system),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_update(GameState::Running)
+                    .label(TickStep::Blocks)
+                    .after(TickStep::Lasers)
+                    .with_system(block_support_system)
+                    .with_system(falling_block_group_system.after(block_support_system))
+                    .with_system(block_falling_system.after(falling_block_group_system))
+                    .with_system(remove_unsupported_block.after(block_falling_system))
+                    // blocks that melt are despawned after support has looked at them
+                    .with_system(block_heat_system.after(remove_unsupported_block))
+                    .with_system(block_melt_system.after(block_heat_system))
+                    .with_system(block_glow_system.after(block_melt_system))
+                    .with_system(block_decimate_system.after(block_glow_system)),
+            )
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::LevelReload)
+                    .label(TickStep::Blocks)
+                    .after(TickStep::Lasers)
+                    .with_system(block_setup_system),
+            );
     }
 }
 
-fn get_blocks_from_map(block_selector: char, block_map: Res<BlockMap>) -> Vec<(usize, usize)> {
-    block_map
-        .0
-        .iter()
-        .enumerate()
-        .filter(|(_, x)| char::from(**x) == block_selector)
-        .map(|(n, _)| {
-            let x: usize = n % (NUMBER_COLS);
-            let y: usize = n / (NUMBER_COLS);
-            (x, y)
-        })
-        .collect::<Vec<(usize, usize)>>()
+/// How far a shot carves into the block it destroys, as a fraction of the
+/// block's side.
+pub const IMPACT_DEPTH: f32 = 0.5;
+/// Pieces whose centre is closer than this many piece sides to the path of
+/// the shot are blown away.
+pub const IMPACT_REMOVE_RADIUS: f32 = 0.75;
+/// Pieces closer than this many piece sides are left damaged, halfway to
+/// melting.
+pub const IMPACT_DAMAGE_RADIUS: f32 = 1.5;
+
+enum PieceDamage {
+    Intact,
+    Damaged,
+    Removed,
 }
 
-pub fn block_support_setup_system(
-    mut commands: Commands,
-    game_textures: Res<GameTextures>,
-    block_map: Res<BlockMap>,
-) {
-    let blocks = get_blocks_from_map('S', block_map);
-    dbg!(&blocks);
-
-    for (x, y) in blocks {
-        let (screen_x, screen_y) = (
-            x as f32 * GRID_WIDTH - SCREEN_WIDTH / 2. + BLOCK_SUPPORT_SPRITE_OFFSET,
-            -(y as f32 * GRID_WIDTH - SCREEN_HEIGHT / 2. + BLOCK_SUPPORT_SPRITE_OFFSET),
-        );
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: game_textures.block_support.clone(),
-                transform: Transform {
-                    translation: Vec3::new(screen_x, screen_y, 2.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(SpriteSize::from(BLOCK_SUPPORT_SPRITE_SIZE))
-            .insert(Block)
-            .insert(BlockHeat::new())
-            .insert(BlockSize::Large(64));
+/// How badly the shot that destroyed a block hit the piece at `centre`. The
+/// shot carves along its direction from the impact point.
+fn piece_damage(impact: &Impact, centre: Vec2, piece_side: f32, block_side: f32) -> PieceDamage {
+    let start = impact.point;
+    let end = start + impact.direction * block_side * IMPACT_DEPTH;
+    let along = (end - start).length_squared();
+    let t = if along > 0. {
+        ((centre - start).dot(end - start) / along).clamp(0., 1.)
+    } else {
+        0.
+    };
+    let distance = centre.distance(start + (end - start) * t) / piece_side;
+
+    if distance < IMPACT_REMOVE_RADIUS {
+        PieceDamage::Removed
+    } else if distance < IMPACT_DAMAGE_RADIUS {
+        PieceDamage::Damaged
+    } else {
+        PieceDamage::Intact
     }
 }
 
-pub fn block_map_setup_system(mut commands: Commands) {
-    let map_input = File::open("assets/map.txt").expect("no Map file found.");
-
-    let mut reader = BufReader::new(map_input);
-    let mut file_buffer: Vec<u8> = Vec::new();
-
-    if reader.read_to_end(&mut file_buffer).is_ok() {
-        file_buffer.retain(|x| char::from(*x) != '\n');
+/// Spawns one block of the given material and adds it to the grid.
+pub fn spawn_block(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    block_grid: &mut BlockGrid,
+    materials: &MaterialRegistry,
+    material_id: MaterialId,
+    position: Vec3,
+) -> Entity {
+    let material = materials.get(material_id);
+    let block_size = material.tier.block_size();
+    let sprite_size = material.tier.sprite_size();
+    let mut block = commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(sprite_size.0),
+            ..Default::default()
+        },
+        texture: asset_server.load(material.sprite.as_str()),
+        transform: Transform {
+            translation: position,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    block
+        .insert(sprite_size)
+        .insert(Block)
+        .insert(material_id)
+        .insert(BlockHeat::new())
+        .insert(material.thermal())
+        .insert(Density(material.density))
+        .insert(Absorption(material.absorption))
+        .insert(block_size.clone())
+        .insert(LevelEntity);
+    if material.support {
+        block.insert(Support);
+    }
+    if material.optics != Optics::Absorb {
+        block.insert(material.optics);
+    }
+    if let Some(explosive) = material.explosive {
+        block.insert(explosive);
+    }
+    let entity = block.id();
+    block_grid.insert(&block_size, position, entity);
+    entity
+}
 
-        commands.insert_resource(BlockMap(file_buffer));
+/// Spawns every block of the level from the material its tile stands for.
+pub fn block_setup_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut block_grid: ResMut<BlockGrid>,
+    level: CurrentLevel,
+    materials: Materials,
+) {
+    let level = level.get().unwrap();
+    let materials = materials.get().unwrap();
+
+    for (x, y, name) in level.blocks() {
+        let material_id = match materials.id(name) {
+            Some(material_id) => material_id,
+            None => {
+                warn!(
+                    "level \"{}\" uses unknown material \"{}\"",
+                    level.name, name
+                );
+                continue;
+            }
+        };
+        let position = level.grid_to_world(x, y).extend(2.0);
+        let block = spawn_block(
+            &mut commands,
+            &asset_server,
+            &mut block_grid,
+            materials,
+            material_id,
+            position,
+        );
+        commands.entity(block).insert(LevelBlock);
     }
 }
 
+/// Splits destroyed blocks into the pieces their material decimates into,
+/// laid out to fill the space the block took up. When a laser destroyed the
+/// block, the pieces along its path are removed or damaged instead, so the
+/// shot carves a hole.
 pub fn block_decimate_system(
     mut commands: Commands,
-    game_textures: Res<GameTextures>,
-    query: Query<(&BlockToDecimate, &BlockSize)>,
+    asset_server: Res<AssetServer>,
+    mut block_grid: ResMut<BlockGrid>,
+    materials: Materials,
+    query: Query<(&BlockToDecimate, &MaterialId, &SpriteSize, Option<&Impact>)>,
 ) {
-    for (target_block, block_size) in query.iter() {
-        if let BlockSize::Medium(_) = block_size {
-            continue;
-        }
-        const MEDIUM_ROW_RATIO: f32 = 4.0; // :large
-        const OFFSET: f32 = 24.;
-
-        let x = target_block.0.x - OFFSET;
-        let y = target_block.0.y - OFFSET;
-
-        for row in 0..MEDIUM_ROW_RATIO as i32 {
-            for col in 0..MEDIUM_ROW_RATIO as i32 {
-                commands
-                    .spawn_bundle(SpriteBundle {
-                        texture: game_textures.block_medium.clone(),
-                        transform: Transform {
-                            translation: Vec3::new(
-                                x + row as f32 * BLOCK_MEDIUM_SIZE,
-                                y + col as f32 * BLOCK_MEDIUM_SIZE,
-                                10.0,
-                            ),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    })
-                    .insert(SpriteSize::from(BLOCK_MEDIUM_SPRITE_SIZE))
-                    .insert(Block)
-                    .insert(BlockHeat::new())
-                    .insert(BlockSize::Medium(10));
+    let materials = match materials.get() {
+        Some(materials) => materials,
+        None => return,
+    };
+
+    for (target_block, material_id, sprite_size, impact) in query.iter() {
+        let piece_material = match materials.decimates_into(*material_id) {
+            Some(piece_material) => piece_material,
+            None => continue,
+        };
+        let pieces = materials.get(*material_id).pieces;
+        let piece_size = sprite_size.0 / pieces as f32;
+        let corner = target_block.0.truncate() - sprite_size.0 / 2.;
+        let piece_thermal = materials.get(piece_material).thermal();
+
+        for row in 0..pieces {
+            for col in 0..pieces {
+                let centre = corner + Vec2::new(col as f32 + 0.5, row as f32 + 0.5) * piece_size;
+                let damage = impact.map_or(PieceDamage::Intact, |impact| {
+                    piece_damage(impact, centre, piece_size.x, sprite_size.0.x)
+                });
+                if let PieceDamage::Removed = damage {
+                    continue;
+                }
+
+                let piece = spawn_block(
+                    &mut commands,
+                    &asset_server,
+                    &mut block_grid,
+                    materials,
+                    piece_material,
+                    centre.extend(target_block.0.z),
+                );
+                if let PieceDamage::Damaged = damage {
+                    commands.entity(piece).insert(BlockHeat(
+                        (AMBIENT_TEMPERATURE + piece_thermal.melting_point) / 2.,
+                    ));
+                }
             }
         }
+
+        // the pieces may not hold up what the whole block did, e.g. a support
+        commands
+            .spawn()
+            .insert(Unsupported(target_block.0))
+            .insert(LevelEntity);
     }
 }
@@ -0,0 +1,116 @@
+use crate::*;
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+/// Loaders are picked by what follows the first dot, so the campaign needs a
+/// name in front of its `campaign.ron` extension.
+pub const CAMPAIGN_FILE: &str = "main.campaign.ron";
+
+/// Ordered list of level files, relative to the assets folder.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "0b8d5a27-93f4-4c1e-b6a2-57e1d9c40f38"]
+pub struct Campaign {
+    pub levels: Vec<String>,
+}
+
+impl RonAsset for Campaign {
+    const EXTENSIONS: &'static [&'static str] = &["campaign.ron"];
+
+    fn from_ron(source: &str) -> Result<Self, DataError> {
+        let campaign: Campaign = ron::from_str(source)?;
+        if campaign.levels.is_empty() {
+            return Err(DataError {
+                line: 1,
+                column: 1,
+                message: "campaign has no levels".to_string(),
+            });
+        }
+        Ok(campaign)
+    }
+}
+
+pub struct CampaignProgress {
+    pub campaign: Handle<Campaign>,
+    pub level: usize,
+}
+
+/// Running totals for the level being played, reset when it is set up.
+#[derive(Default)]
+pub struct LevelStats {
+    pub shots: u32,
+    /// Blocks of the level's own destroyed so far, not counting the pieces
+    /// they break into.
+    pub blocks_destroyed: u32,
+}
+
+pub fn campaign_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CampaignProgress {
+        campaign: asset_server.load(CAMPAIGN_FILE),
+        level: 0,
+    });
+    commands.insert_resource(LevelHandle(Handle::default()));
+}
+
+pub fn level_stats_setup_system(mut commands: Commands) {
+    commands.insert_resource(LevelStats::default());
+}
+
+pub fn level_complete_check_system(
+    levels: Res<Assets<Level>>,
+    level_handle: Res<LevelHandle>,
+    level_stats: Res<LevelStats>,
+    block_query: Query<Entity, With<Block>>,
+    decimate_query: Query<Entity, With<BlockToDecimate>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let level = match levels.get(&level_handle.0) {
+        Some(level) => level,
+        None => return,
+    };
+
+    let complete = match level.goal {
+        LevelGoal::ClearBlocks => block_query.is_empty() && decimate_query.is_empty(),
+        LevelGoal::DestroyBlocks(count) => level_stats.blocks_destroyed >= count,
+    };
+
+    // a reload queued earlier in the tick goes first, and the level is
+    // checked again once it is back up
+    if complete && state.set(GameState::LevelComplete).is_ok() {
+        info!(
+            "level \"{}\" complete in {} shots, par {}",
+            level.name, level_stats.shots, level.par_shots
+        );
+    }
+}
+
+/// Clears out the finished level and moves on to the next one, if any. A
+/// replay only covers one level, so it ends there.
+pub fn level_complete_system(
+    mut commands: Commands,
+    campaigns: Res<Assets<Campaign>>,
+    replay_player: Option<Res<ReplayPlayer>>,
+    mut progress: ResMut<CampaignProgress>,
+    mut block_grid: ResMut<BlockGrid>,
+    query: Query<Entity, With<LevelEntity>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    block_grid.clear();
+
+    progress.level += 1;
+    let level_count = campaigns
+        .get(&progress.campaign)
+        .map_or(0, |campaign| campaign.levels.len());
+
+    if progress.level < level_count && replay_player.is_none() {
+        state.set(GameState::LevelLoading).unwrap();
+    } else {
+        state.set(GameState::CampaignComplete).unwrap();
+    }
+}
+
+pub fn campaign_complete_system(progress: Res<CampaignProgress>) {
+    info!("campaign complete, {} levels cleared", progress.level);
+}
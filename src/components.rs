@@ -8,19 +8,12 @@ pub struct WinSize {
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub enum GameState {
     StartUp,
+    LevelLoading,
     GameSetup,
     Running,
-}
-
-#[derive(Component)]
-pub struct GameTextures {
-    pub actor_animation_sprite: Handle<TextureAtlas>,
-    pub explosion_animation_sprite: Handle<TextureAtlas>,
-    pub actor: Handle<Image>,
-    pub block_large: Handle<Image>,
-    pub block_medium: Handle<Image>,
-    pub block_support: Handle<Image>,
-    // pub laser: Handle<Image>,
+    LevelReload,
+    LevelComplete,
+    CampaignComplete,
 }
 
 #[derive(Component)]
@@ -29,12 +22,36 @@ pub struct Velocity {
     pub y: f32,
 }
 
+/// Tags everything that belongs to the level being played, so it can be
+/// cleared out when the level is complete.
+#[derive(Component)]
+pub struct LevelEntity;
+
 #[derive(Component)]
 pub struct Actor;
 
 #[derive(Component)]
 pub struct Laser;
 
+/// Power a laser still carries, in full shots, after the blocks it went
+/// through have taken their share.
+#[derive(Component, Clone, Copy)]
+pub struct LaserPower(pub f32);
+
+/// Makes a laser a bomb that explodes with this radius on the first block it
+/// hits, with all of its power.
+#[derive(Component, Clone, Copy)]
+pub struct BlastRadius(pub f32);
+
+/// Where a laser was before this tick's move, so hits are found along the
+/// whole way it went and it can't skip over thin blocks.
+#[derive(Component, Clone, Copy)]
+pub struct PreviousPosition(pub Vec2);
+
+/// The sprite of a continuous beam, stretched from the actor to what it hits.
+#[derive(Component)]
+pub struct Beam;
+
 #[derive(Component, Clone)]
 pub struct SpriteSize(pub Vec2);
 
@@ -47,35 +64,76 @@ impl From<(f32, f32)> for SpriteSize {
 #[derive(Component)]
 pub struct Explosion;
 
-#[derive(Component)]
-pub struct ExplosionToSpawn(pub Vec3);
-
-#[derive(Component)]
-pub struct ExplosionTimer(pub Timer);
+/// An explosion to set off. It heats every block within `radius` pixels of
+/// `position`, by `strength` right at the centre and less further out.
+#[derive(Component, Clone, Copy)]
+pub struct ExplosionToSpawn {
+    pub position: Vec3,
+    pub radius: f32,
+    pub strength: f32,
+}
 
-impl Default for ExplosionTimer {
-    fn default() -> Self {
-        Self(Timer::from_seconds(0.05, true))
+impl ExplosionToSpawn {
+    /// An explosion that is only for show.
+    pub fn visual(position: Vec3) -> Self {
+        ExplosionToSpawn {
+            position,
+            radius: 0.,
+            strength: 0.,
+        }
     }
 }
 
 #[derive(Component)]
 pub struct BlockToDecimate(pub Vec3);
 
+/// Where, and heading which way, the last laser struck a block.
+#[derive(Component, Clone, Copy)]
+pub struct Impact {
+    pub point: Vec2,
+    pub direction: Vec2,
+}
+
 #[derive(Component)]
 pub struct Block;
 
+/// A block the level placed, as opposed to a piece decimated out of one.
+/// Only these count towards `LevelGoal::DestroyBlocks`.
+#[derive(Component)]
+pub struct LevelBlock;
+
+/// Support tiles hold up whatever is connected to them. They are anchored
+/// to the level itself, so they stay put even once everything under them is
+/// gone.
+#[derive(Component)]
+pub struct Support;
+
+/// Weight of a block relative to the default material.
+#[derive(Component, Clone, Copy)]
+pub struct Density(pub f32);
+
+/// Most laser power a block takes from one hit, see `BlockMaterial::absorption`.
+#[derive(Component, Clone, Copy)]
+pub struct Absorption(pub f32);
+
+/// Tier of a block, holding the side of its collision box in pixels.
 #[derive(Component, Clone)]
 pub enum BlockSize {
-    _Small(u8),
+    Small(u8),
     Medium(u8),
     Large(u8),
 }
 
-#[derive(Component)]
-pub struct RawMap(pub Vec<u8>);
-
-#[derive(Component)]
-pub struct BlockMap(pub Vec<u8>);
+impl BlockSize {
+    pub fn side(&self) -> f32 {
+        match self {
+            BlockSize::Small(side) | BlockSize::Medium(side) | BlockSize::Large(side) => {
+                *side as f32
+            }
+        }
+    }
+}
 
+/// Lasers that flew off the screen this tick. They are still around until
+/// the tick's commands are applied, so hits have to skip them.
 pub struct DespawnedList(pub HashSet<Entity>);
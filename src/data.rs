@@ -0,0 +1,182 @@
+use bevy::{
+    asset::{Asset, AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::{Resource, SystemParam},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use std::{fmt, marker::PhantomData};
+
+/// A problem in a data file, at a line and column counted from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl DataError {
+    /// Points at the first occurrence of `text` in quotes, or the start of
+    /// the file if there is none.
+    pub fn at_quoted(source: &str, text: &str, message: String) -> Self {
+        let offset = source.find(&format!("\"{}\"", text)).map_or(0, |n| n + 1);
+        let (line, column) = line_and_column(source, offset);
+        DataError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    /// Points at the `n`th place, counting from 0, where something is given
+    /// `name` as its `name`, like the second definition of a material
+    /// defined twice. Falls back to the first occurrence of `name` in quotes.
+    pub fn at_name(source: &str, name: &str, n: usize, message: String) -> Self {
+        let quoted = format!("\"{}\"", name);
+        let offset = field_keys(source, "name")
+            .into_iter()
+            .filter_map(|key| {
+                let value = source[key + "name".len()..]
+                    .trim_start()
+                    .strip_prefix(':')?
+                    .trim_start();
+                value
+                    .starts_with(&quoted)
+                    .then(|| source.len() - value.len())
+            })
+            .nth(n)
+            .or_else(|| source.find(&quoted))
+            .map_or(0, |n| n + 1);
+        let (line, column) = line_and_column(source, offset);
+        DataError {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl From<ron::Error> for DataError {
+    fn from(err: ron::Error) -> Self {
+        // serde's own errors, like a missing field, come without a position,
+        // so point at the start of the file like other whole-file problems
+        DataError {
+            line: err.position.line.max(1),
+            column: err.position.col.max(1),
+            message: err.code.to_string(),
+        }
+    }
+}
+
+pub(crate) fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let line = source[..offset].matches('\n').count() + 1;
+    let line_start = source[..offset].rfind('\n').map_or(0, |n| n + 1);
+    (line, source[line_start..offset].chars().count() + 1)
+}
+
+/// Offsets of every `field:` key in `source`, skipping over strings, chars
+/// and line comments so that text in them doesn't count.
+pub(crate) fn field_keys(source: &str, field: &str) -> Vec<usize> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut keys = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                while let Some((_, inner)) = chars.next() {
+                    if inner == '\\' {
+                        chars.next();
+                    } else if inner == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().map(|&(_, next)| next) == Some('/') => {
+                for (_, inner) in chars.by_ref() {
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ if source[offset..].starts_with(field) => {
+                let before = source[..offset].chars().next_back();
+                let after = &source[offset + field.len()..];
+                if !before.is_some_and(is_ident)
+                    && !after.starts_with(is_ident)
+                    && after.trim_start().starts_with(':')
+                {
+                    keys.push(offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+/// An asset read from a RON file on its own and checked as it loads.
+pub trait RonAsset: Asset + Sized {
+    /// What its file names end in, after the first dot.
+    const EXTENSIONS: &'static [&'static str];
+
+    fn from_ron(source: &str) -> Result<Self, DataError>;
+}
+
+/// Loads any `RonAsset`, reporting problems with the file's path.
+pub struct RonLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for RonLoader<T> {
+    fn default() -> Self {
+        RonLoader(PhantomData)
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?;
+            let asset = T::from_ron(source)
+                .map_err(|err| anyhow::anyhow!("{}:{}", load_context.path().display(), err))?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
+
+/// A resource holding the handle of the asset the game is using, like
+/// `MaterialsHandle`.
+pub trait AssetHandle: Resource {
+    type Asset: Asset;
+
+    fn handle(&self) -> &Handle<Self::Asset>;
+}
+
+/// The asset behind the handle in `H`, for systems that only need to read it.
+#[derive(SystemParam)]
+pub struct Loaded<'w, 's, H: AssetHandle> {
+    assets: Res<'w, Assets<H::Asset>>,
+    handle: Res<'w, H>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's, H: AssetHandle> Loaded<'w, 's, H> {
+    pub fn get(&self) -> Option<&H::Asset> {
+        self.assets.get(self.handle.handle())
+    }
+}
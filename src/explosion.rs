@@ -0,0 +1,89 @@
+use crate::*;
+use bevy::prelude::*;
+
+pub const EXPLOSION_CLIP: &str = "explosion";
+
+/// Turns `ExplosionToSpawn` markers into animated explosions that heat the
+/// blocks around them.
+pub(crate) struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_update(GameState::Running)
+                .label(TickStep::Explosions)
+                .after(TickStep::Blocks)
+                .with_system(explosion_damage_system)
+                .with_system(explosion_to_spawn_system.after(explosion_damage_system)),
+        );
+    }
+}
+
+type ExplosionTargets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static SpriteSize,
+        &'static mut BlockHeat,
+        &'static Thermal,
+    ),
+    With<Block>,
+>;
+
+/// Heats every block in reach of a new explosion, falling off in a straight
+/// line from its full strength at the centre to nothing at its radius.
+/// Distance is measured to the nearest edge of a block, so big blocks next
+/// to it take the full blast. Blocks it destroys are removed next tick, and
+/// set off their own explosions then if they are explosive.
+pub fn explosion_damage_system(
+    mut commands: Commands,
+    block_grid: Res<BlockGrid>,
+    query: Query<&ExplosionToSpawn>,
+    mut block_query: ExplosionTargets,
+) {
+    for explosion in query.iter() {
+        if explosion.radius <= 0. {
+            continue;
+        }
+        let centre = explosion.position.truncate();
+        let reach = Vec2::splat(explosion.radius * 2.);
+        for entity in block_grid.candidates(explosion.position, reach) {
+            let (transform, sprite_size, mut heat, thermal) = match block_query.get_mut(entity) {
+                Ok(block) => block,
+                Err(_) => continue,
+            };
+            let block_centre = transform.translation.truncate();
+            let half_size = sprite_size.0 / 2.;
+            let nearest = centre.clamp(block_centre - half_size, block_centre + half_size);
+            let falloff = 1. - nearest.distance(centre) / explosion.radius;
+            if falloff <= 0. {
+                continue;
+            }
+            heat.add_energy(explosion.strength * falloff, thermal);
+
+            // decimation carves the block out from the side facing the blast
+            commands.entity(entity).insert(Impact {
+                point: nearest,
+                direction: (block_centre - centre).normalize_or_zero(),
+            });
+        }
+    }
+}
+
+/// Sets off the explosion animation for every `ExplosionToSpawn`, once it
+/// has done its damage.
+pub fn explosion_to_spawn_system(
+    mut commands: Commands,
+    atlases: Res<AnimationAtlases>,
+    query: Query<(Entity, &ExplosionToSpawn)>,
+) {
+    for (entity, explosion_to_spawn) in query.iter() {
+        let transform = Transform::from_translation(explosion_to_spawn.position);
+        let explosion = spawn_effect(&mut commands, &atlases, EXPLOSION_CLIP, transform);
+        commands.entity(explosion).insert(Explosion);
+
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,228 @@
+use crate::*;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+#[derive(Component)]
+pub struct Unsupported(pub Vec3);
+
+#[derive(Component)]
+pub struct BlockFalling;
+
+/// Falling blocks that move together, keyed by the block the group was found from.
+#[derive(Component)]
+pub struct FallingGroup(pub Entity);
+
+/// Blocks closer than this count as touching.
+pub const TOUCH_TOLERANCE: f32 = 0.5;
+
+/// Blocks are connected when they share part of an edge, not just a corner.
+pub fn blocks_touch(a: Vec3, a_size: Vec2, b: Vec3, b_size: Vec2) -> bool {
+    let gap = (a - b).truncate().abs() - (a_size + b_size) / 2.;
+    (gap.x <= TOUCH_TOLERANCE && gap.y < -TOUCH_TOLERANCE)
+        || (gap.y <= TOUCH_TOLERANCE && gap.x < -TOUCH_TOLERANCE)
+}
+
+fn level_floor(level: &CurrentLevel) -> f32 {
+    level.get().map_or(-SCREEN_HEIGHT / 2., Level::floor)
+}
+
+type UngroupedFallingBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Transform, &'static SpriteSize),
+    (With<BlockFalling>, Without<FallingGroup>),
+>;
+
+/// Turns blocks that just lost their support into rigid groups: blocks that
+/// started falling together and touch each other are flood filled into one
+/// group that falls as a single body.
+pub fn falling_block_group_system(
+    mut commands: Commands,
+    block_grid: Res<BlockGrid>,
+    flagged_query: Query<Entity, (Added<BlockFalling>, Without<FallingGroup>)>,
+    block_query: UngroupedFallingBlocks,
+) {
+    let mut visited: HashSet<Entity> = HashSet::new();
+
+    for seed in flagged_query.iter() {
+        if !visited.insert(seed) || !block_query.contains(seed) {
+            continue;
+        }
+
+        let mut group = vec![seed];
+        let mut next = 0;
+        while next < group.len() {
+            let entity = group[next];
+            next += 1;
+
+            let (transform, sprite_size) = block_query.get(entity).unwrap();
+            let position = transform.translation;
+
+            let reach = sprite_size.0 + 2. * TOUCH_TOLERANCE;
+            for neighbour in block_grid.candidates(position, reach) {
+                if visited.contains(&neighbour) {
+                    continue;
+                }
+                if let Ok((neighbour_transform, neighbour_size)) = block_query.get(neighbour) {
+                    if blocks_touch(
+                        position,
+                        sprite_size.0,
+                        neighbour_transform.translation,
+                        neighbour_size.0,
+                    ) {
+                        visited.insert(neighbour);
+                        group.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        for entity in group {
+            commands
+                .entity(entity)
+                .insert(FallingGroup(seed))
+                .insert(Velocity { x: 0., y: 0. });
+        }
+    }
+}
+
+pub fn remove_unsupported_block(mut commands: Commands, query: Query<Entity, With<Unsupported>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn(); // move to next frame
+    }
+}
+
+type StandingBlocks<'w, 's> =
+    Query<'w, 's, (&'static Transform, &'static SpriteSize), (With<Block>, Without<BlockFalling>)>;
+
+type FallingBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static SpriteSize,
+        &'static BlockSize,
+        &'static FallingGroup,
+    ),
+    With<BlockFalling>,
+>;
+
+/// How fast unsupported blocks fall, in pixels per second.
+pub struct FallingConfig {
+    pub gravity: f32,
+    pub terminal_velocity: f32,
+}
+
+impl Default for FallingConfig {
+    fn default() -> Self {
+        FallingConfig {
+            gravity: 980.,
+            terminal_velocity: 640.,
+        }
+    }
+}
+
+/// Sent for every block of a group when the group lands.
+pub struct BlockLanded {
+    pub entity: Entity,
+    pub impact_speed: f32,
+}
+
+/// Landed blocks are snapped so their bottom edge sits on this grid.
+fn snap_to_grid(y: f32) -> f32 {
+    (y / BLOCK_SMALL_SIZE).round() * BLOCK_SMALL_SIZE
+}
+
+struct GroupFall {
+    velocity: f32,
+    drop: f32,
+    landed: bool,
+    bottom: f32,
+}
+
+/// Moves each falling group down as one body under gravity, and stops the
+/// whole group as soon as any of its blocks lands on a standing block or the
+/// floor.
+#[allow(clippy::too_many_arguments)]
+pub fn block_falling_system(
+    mut commands: Commands,
+    clock: Res<GameClock>,
+    falling_config: Res<FallingConfig>,
+    mut block_grid: ResMut<BlockGrid>,
+    level: CurrentLevel,
+    mut landed_events: EventWriter<BlockLanded>,
+    mut falling_query: FallingBlocks,
+    collision_query: StandingBlocks,
+) {
+    let delta = clock.delta_seconds();
+    let floor = level_floor(&level);
+    let mut groups: HashMap<Entity, GroupFall> = HashMap::new();
+
+    for (_, transform, velocity, sprite_size, _, group) in falling_query.iter() {
+        let group_fall = groups.entry(group.0).or_insert_with(|| {
+            let velocity = (velocity.y - falling_config.gravity * delta)
+                .max(-falling_config.terminal_velocity);
+            GroupFall {
+                velocity,
+                drop: -velocity * delta,
+                landed: false,
+                bottom: 0.,
+            }
+        });
+
+        // the furthest this block can go before it touches something
+        let position = transform.translation;
+        let bottom = position.y - sprite_size.0.y / 2.;
+        let mut clearance = bottom - floor;
+        let sweep = Vec2::new(sprite_size.0.x, sprite_size.0.y + 2. * group_fall.drop);
+        for (collision_transform, collision_size) in block_grid
+            .candidates(position, sweep)
+            .into_iter()
+            .filter_map(|entity| collision_query.get(entity).ok())
+        {
+            let other = collision_transform.translation;
+            let overlap_x =
+                (position.x - other.x).abs() - (sprite_size.0.x + collision_size.0.x) / 2.;
+            let top = other.y + collision_size.0.y / 2.;
+            if overlap_x < -TOUCH_TOLERANCE && top <= bottom + TOUCH_TOLERANCE {
+                clearance = clearance.min(bottom - top);
+            }
+        }
+
+        if clearance <= group_fall.drop {
+            group_fall.drop = clearance.max(0.);
+            group_fall.landed = true;
+            group_fall.bottom = bottom;
+        }
+    }
+
+    for (entity, mut transform, mut velocity, _, block_size, group) in falling_query.iter_mut() {
+        let group_fall = &groups[&group.0];
+        let from = transform.translation;
+
+        if group_fall.landed {
+            // landed: line the block that touched down up with the grid
+            let landed_bottom = group_fall.bottom - group_fall.drop;
+            let snap = snap_to_grid(landed_bottom) - landed_bottom;
+            transform.translation.y -= group_fall.drop - snap;
+
+            landed_events.send(BlockLanded {
+                entity,
+                impact_speed: -group_fall.velocity,
+            });
+            commands
+                .entity(entity)
+                .remove::<BlockFalling>()
+                .remove::<FallingGroup>()
+                .remove::<Velocity>();
+        } else {
+            transform.translation.y -= group_fall.drop;
+            velocity.y = group_fall.velocity;
+        }
+        block_grid.update(block_size, from, transform.translation, entity);
+    }
+}
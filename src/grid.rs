@@ -0,0 +1,89 @@
+use crate::*;
+use bevy::{prelude::*, utils::HashMap};
+
+const TIERS: usize = 3;
+
+/// Spatial index of every `Block`, one map of integer cells per `BlockSize`
+/// tier. A block lives in the cell that contains its centre, so lookups are a
+/// broad phase only; callers still run `collide` on the candidates.
+#[derive(Default)]
+pub struct BlockGrid {
+    tiers: [HashMap<IVec2, Vec<Entity>>; TIERS],
+}
+
+fn tier(size: &BlockSize) -> usize {
+    match size {
+        BlockSize::Small(_) => 0,
+        BlockSize::Medium(_) => 1,
+        BlockSize::Large(_) => 2,
+    }
+}
+
+/// Side of a grid cell for each tier, indexed like `BlockGrid::tiers`.
+const CELL_SIZES: [f32; TIERS] = [BLOCK_SMALL_SIZE, BLOCK_MEDIUM_SIZE, GRID_WIDTH];
+
+fn cell_in_tier(tier: usize, position: Vec2) -> IVec2 {
+    (position / CELL_SIZES[tier]).floor().as_ivec2()
+}
+
+impl BlockGrid {
+    pub fn cell(size: &BlockSize, position: Vec3) -> IVec2 {
+        cell_in_tier(tier(size), position.truncate())
+    }
+
+    pub fn insert(&mut self, size: &BlockSize, position: Vec3, entity: Entity) {
+        self.tiers[tier(size)]
+            .entry(Self::cell(size, position))
+            .or_default()
+            .push(entity);
+    }
+
+    pub fn remove(&mut self, size: &BlockSize, position: Vec3, entity: Entity) {
+        let cells = &mut self.tiers[tier(size)];
+        let cell = Self::cell(size, position);
+        if let Some(entities) = cells.get_mut(&cell) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Keeps a moving block in the right cell.
+    pub fn update(&mut self, size: &BlockSize, from: Vec3, to: Vec3, entity: Entity) {
+        if Self::cell(size, from) != Self::cell(size, to) {
+            self.remove(size, from, entity);
+            self.insert(size, to, entity);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cells in self.tiers.iter_mut() {
+            cells.clear();
+        }
+    }
+
+    /// Blocks whose cell could hold something overlapping the box centred on
+    /// `position`, smallest tier first and then row by row, so the order only
+    /// depends on where the blocks are.
+    pub fn candidates(&self, position: Vec3, size: Vec2) -> Vec<Entity> {
+        let mut found = Vec::new();
+        for (tier, cells) in self.tiers.iter().enumerate() {
+            if cells.is_empty() {
+                continue;
+            }
+            // a block reaches half a cell past its centre, so widen the box by that much
+            let reach = size / 2. + CELL_SIZES[tier] / 2.;
+            let min = cell_in_tier(tier, position.truncate() - reach);
+            let max = cell_in_tier(tier, position.truncate() + reach);
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    if let Some(entities) = cells.get(&IVec2::new(x, y)) {
+                        found.extend(entities.iter().copied());
+                    }
+                }
+            }
+        }
+        found
+    }
+}
@@ -0,0 +1,303 @@
+use crate::*;
+use bevy::{
+    asset::AssetPlugin,
+    ecs::{event::Events, system::CommandQueue},
+    input::{gamepad::GamepadEventRaw, InputPlugin},
+    prelude::*,
+};
+
+const MATERIALS: &str = include_str!("../assets/blocks.materials.ron");
+const WEAPONS: &str = include_str!("../assets/actor.weapons.ron");
+const ANIMATIONS: &str = include_str!("../assets/effects.animations.ron");
+
+pub enum ScriptedKey {
+    Press(KeyCode),
+    Release(KeyCode),
+}
+
+/// A block, as far as a test is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockState {
+    pub position: Vec2,
+    pub side: f32,
+    pub material: String,
+}
+
+/// Runs `SpritexelPlugin` on `MinimalPlugins`, with no window, GPU or images,
+/// stepping one fixed tick per update and feeding it scripted key presses
+/// and gamepad events.
+pub struct Harness {
+    app: App,
+    level_id: String,
+    tick: u64,
+    script: Vec<(u64, ScriptedKey)>,
+}
+
+impl Harness {
+    /// Starts the level in `level_source`, using the game's own materials
+    /// and weapons.
+    pub fn new(level_source: &str) -> Self {
+        Self::with_materials(level_source, MATERIALS)
+    }
+
+    /// Starts the level in `level_source` with the materials in
+    /// `materials_source` instead of the game's own.
+    pub fn with_materials(level_source: &str, materials_source: &str) -> Self {
+        let materials = MaterialRegistry::from_ron(materials_source).unwrap();
+        let level = Level::from_ron(level_source, &materials).unwrap();
+        Self::start(materials, level, level_source)
+    }
+
+    /// Starts a level from the game's assets, e.g. `levels/01.level.ron`.
+    pub fn load(path: &str) -> Self {
+        let file = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), path);
+        let source = std::fs::read_to_string(&file).unwrap();
+        let materials = MaterialRegistry::from_ron(MATERIALS).unwrap();
+        let mut level =
+            Level::from_ron(&source, &materials).unwrap_or_else(|err| panic!("{}:{}", file, err));
+        level.id = path.to_string();
+        Self::start(materials, level, &source)
+    }
+
+    /// Starts the level `replay` was recorded on and plays it back.
+    pub fn replay(replay: Replay) -> Self {
+        let mut harness = Self::load(&replay.level);
+        harness.app.insert_resource(ReplayPlayer::new(replay));
+        harness
+    }
+
+    /// Plays the level files in `levels` as a campaign, loading each one
+    /// through the asset server the way the game does. Loading takes real
+    /// time, so step until the level is running.
+    pub fn campaign(levels: &[&str]) -> Self {
+        let materials = MaterialRegistry::from_ron(MATERIALS).unwrap();
+        let mut app = Self::app(materials, GameState::LevelLoading);
+        let campaign = Campaign {
+            levels: levels.iter().map(|level| level.to_string()).collect(),
+        };
+        let campaign = app.world.resource_mut::<Assets<Campaign>>().add(campaign);
+        app.insert_resource(CampaignProgress { campaign, level: 0 })
+            .insert_resource(LevelHandle(Handle::default()));
+        Self::run(app, String::new())
+    }
+
+    fn start(materials: MaterialRegistry, level: Level, level_source: &str) -> Self {
+        let level_id = level.id.clone();
+        level
+            .check_weapons(level_source, &Arsenal::from_ron(WEAPONS).unwrap())
+            .unwrap();
+
+        // skip the campaign and go straight to setting up the level
+        let mut app = Self::app(materials, GameState::GameSetup);
+        let level = app.world.resource_mut::<Assets<Level>>().add(level);
+        app.insert_resource(LevelHandle(level));
+        Self::run(app, level_id)
+    }
+
+    /// The game on `MinimalPlugins`, starting in `state` with the materials,
+    /// weapons and animations already loaded.
+    fn app(materials: MaterialRegistry, state: GameState) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(SpritexelPlugin)
+            .insert_resource(WinSize {
+                w: SCREEN_WIDTH,
+                h: SCREEN_HEIGHT,
+            })
+            .insert_resource(GameClock {
+                step_every_update: true,
+                ..Default::default()
+            })
+            .insert_resource(CampaignProgress {
+                campaign: Handle::default(),
+                level: 0,
+            })
+            .insert_resource(State::new(state));
+
+        let materials = app
+            .world
+            .resource_mut::<Assets<MaterialRegistry>>()
+            .add(materials);
+        let arsenal = Arsenal::from_ron(WEAPONS).unwrap();
+        let arsenal = app.world.resource_mut::<Assets<Arsenal>>().add(arsenal);
+        let animations = AnimationLibrary::from_ron(ANIMATIONS).unwrap();
+        let animations = app
+            .world
+            .resource_mut::<Assets<AnimationLibrary>>()
+            .add(animations);
+        app.insert_resource(MaterialsHandle(materials))
+            .insert_resource(ArsenalHandle(arsenal))
+            .insert_resource(AnimationsHandle(animations));
+        app
+    }
+
+    fn run(mut app: App, level_id: String) -> Self {
+        // spawn the level before the first scripted tick
+        app.update();
+
+        Harness {
+            app,
+            level_id,
+            tick: 0,
+            script: Vec::new(),
+        }
+    }
+
+    /// Records the actor's input from here on; see `recording`.
+    pub fn record(&mut self) {
+        let recorder = ReplayRecorder::new(None, &self.level_id);
+        self.app.insert_resource(recorder);
+    }
+
+    pub fn recording(&self) -> Replay {
+        self.app.world.resource::<ReplayRecorder>().replay.clone()
+    }
+
+    /// Played back the whole replay it was started with.
+    pub fn replay_finished(&self) -> bool {
+        let tick = self.app.world.resource::<GameClock>().tick;
+        self.app.world.resource::<ReplayPlayer>().finished(tick)
+    }
+
+    /// Reads input through `bindings` instead of the bindings file.
+    pub fn bind(&mut self, bindings: ActionBindings) {
+        let bindings = self
+            .app
+            .world
+            .resource_mut::<Assets<ActionBindings>>()
+            .add(bindings);
+        self.app.insert_resource(BindingsHandle(bindings));
+    }
+
+    /// Sends `event` from the first gamepad, the way a real one would, in
+    /// time for the next `step`. It has to be `Connected` before its buttons
+    /// and sticks count.
+    pub fn gamepad(&mut self, event: GamepadEventType) {
+        self.app
+            .world
+            .resource_mut::<Events<GamepadEventRaw>>()
+            .send(GamepadEventRaw(Gamepad(0), event));
+    }
+
+    /// The tick the next `step` runs, which scripted keys are timed against.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn script(&mut self, tick: u64, key: ScriptedKey) {
+        self.script.push((tick, key));
+    }
+
+    /// Presses `key` on `tick` and lets go on the next one.
+    pub fn tap(&mut self, tick: u64, key: KeyCode) {
+        self.script(tick, ScriptedKey::Press(key));
+        self.script(tick + 1, ScriptedKey::Release(key));
+    }
+
+    pub fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            let mut input = self.app.world.resource_mut::<Input<KeyCode>>();
+            input.clear();
+            for (tick, key) in self.script.iter() {
+                if *tick != self.tick {
+                    continue;
+                }
+                match key {
+                    ScriptedKey::Press(key) => input.press(*key),
+                    ScriptedKey::Release(key) => input.release(*key),
+                }
+            }
+
+            self.app.update();
+            self.tick += 1;
+        }
+    }
+
+    /// Steps until `done` holds, for at most `max_ticks`. Returns whether it
+    /// ever did.
+    pub fn step_until(&mut self, max_ticks: u64, mut done: impl FnMut(&mut Self) -> bool) -> bool {
+        for _ in 0..max_ticks {
+            if done(self) {
+                return true;
+            }
+            self.step(1);
+        }
+        done(self)
+    }
+
+    pub fn state(&self) -> GameState {
+        self.app
+            .world
+            .resource::<State<GameState>>()
+            .current()
+            .clone()
+    }
+
+    pub fn world(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    pub fn count<C: Component>(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<Entity, With<C>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    /// Fires a laser of `power` the size of the laser weapon's shots, centred
+    /// on `position` and moving at `velocity`.
+    pub fn fire(&mut self, position: Vec2, velocity: Vec2, power: f32) -> Entity {
+        let arsenal = Arsenal::from_ron(WEAPONS).unwrap();
+        let size = Vec2::from(arsenal.get("laser").unwrap().size);
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &self.app.world);
+        let laser = spawn_laser(
+            &mut commands,
+            Handle::default(),
+            size,
+            position,
+            velocity,
+            power,
+        );
+        queue.apply(&mut self.app.world);
+        laser
+    }
+
+    /// How much hotter than its surroundings the block centred on `position` is.
+    pub fn heat_of(&mut self, position: Vec2) -> f32 {
+        let world = &mut self.app.world;
+        let mut query = world.query_filtered::<(&Transform, &BlockHeat), With<Block>>();
+        let (_, heat) = query
+            .iter(world)
+            .find(|(transform, _)| transform.translation.truncate() == position)
+            .unwrap_or_else(|| panic!("no block at {}", position));
+        heat.0 - AMBIENT_TEMPERATURE
+    }
+
+    /// Every block, bottom row first and then left to right.
+    pub fn blocks(&mut self) -> Vec<BlockState> {
+        let world = &mut self.app.world;
+        let handle = world.resource::<MaterialsHandle>().0.clone();
+        let mut query = world.query::<(&Transform, &BlockSize, &MaterialId)>();
+        let registries = world.resource::<Assets<MaterialRegistry>>();
+        let materials = registries.get(&handle).unwrap();
+
+        let mut blocks: Vec<BlockState> = query
+            .iter(world)
+            .map(|(transform, block_size, material_id)| BlockState {
+                position: transform.translation.truncate(),
+                side: block_size.side(),
+                material: materials.get(*material_id).name.clone(),
+            })
+            .collect();
+        blocks.sort_by(|a, b| {
+            (a.position.y, a.position.x)
+                .partial_cmp(&(b.position.y, b.position.x))
+                .unwrap()
+        });
+        blocks
+    }
+}
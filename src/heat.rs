@@ -0,0 +1,209 @@
+use crate::*;
+use bevy::{prelude::*, utils::HashMap};
+
+/// Temperature every block starts at and cools back towards.
+pub const AMBIENT_TEMPERATURE: f32 = 20.;
+/// Fraction of the difference to ambient a block loses per second.
+pub const COOLING_RATE: f32 = 0.2;
+/// Blocks this close to ambient are left alone by cooling and conduction.
+const HEAT_EPSILON: f32 = 0.5;
+
+/// Temperature of a block.
+#[derive(Component)]
+pub struct BlockHeat(pub f32);
+
+impl Default for BlockHeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockHeat {
+    pub fn new() -> Self {
+        BlockHeat(AMBIENT_TEMPERATURE)
+    }
+
+    /// Adds `energy` to the block, warming it according to its capacity.
+    pub fn add_energy(&mut self, energy: f32, thermal: &Thermal) {
+        self.0 += energy / thermal.capacity;
+    }
+}
+
+/// How a kind of block takes, spreads and survives heat.
+#[derive(Component, Clone, Copy)]
+pub struct Thermal {
+    /// Energy needed to warm the block by one degree.
+    pub capacity: f32,
+    /// The block is destroyed once it gets this hot.
+    pub melting_point: f32,
+    /// Energy per second per degree of difference passed to touching blocks.
+    pub conductivity: f32,
+}
+
+type HeatBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static SpriteSize,
+        &'static mut BlockHeat,
+        &'static Thermal,
+    ),
+    With<Block>,
+>;
+
+/// Cools warm blocks towards ambient and conducts heat from warm blocks into
+/// the blocks touching them.
+pub fn block_heat_system(clock: Res<GameClock>, block_grid: Res<BlockGrid>, mut query: HeatBlocks) {
+    let delta = clock.delta_seconds();
+    let mut changes: HashMap<Entity, f32> = HashMap::new();
+
+    for (entity, transform, sprite_size, heat, thermal) in query.iter() {
+        if heat.0 - AMBIENT_TEMPERATURE < HEAT_EPSILON {
+            continue;
+        }
+
+        *changes.entry(entity).or_default() -=
+            (heat.0 - AMBIENT_TEMPERATURE) * COOLING_RATE * delta;
+
+        let position = transform.translation;
+        let reach = sprite_size.0 + 2. * TOUCH_TOLERANCE;
+        for neighbour in block_grid.candidates(position, reach) {
+            let (_, neighbour_transform, neighbour_size, neighbour_heat, neighbour_thermal) =
+                match query.get(neighbour) {
+                    Ok(block) => block,
+                    Err(_) => continue,
+                };
+            // each pair is handled once, from its hotter side
+            if neighbour_heat.0 >= heat.0
+                || !blocks_touch(
+                    position,
+                    sprite_size.0,
+                    neighbour_transform.translation,
+                    neighbour_size.0,
+                )
+            {
+                continue;
+            }
+
+            let conductivity = thermal.conductivity.min(neighbour_thermal.conductivity);
+            let energy = conductivity * (heat.0 - neighbour_heat.0) * delta;
+            *changes.entry(entity).or_default() -= energy / thermal.capacity;
+            *changes.entry(neighbour).or_default() += energy / neighbour_thermal.capacity;
+        }
+    }
+
+    for (entity, change) in changes {
+        if let Ok((_, _, _, mut heat, _)) = query.get_mut(entity) {
+            heat.0 += change;
+        }
+    }
+}
+
+type MeltingBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static SpriteSize,
+        &'static BlockSize,
+        &'static MaterialId,
+        &'static BlockHeat,
+        &'static Thermal,
+        Option<&'static Impact>,
+        Option<&'static Explosive>,
+        Option<&'static LevelBlock>,
+    ),
+    With<Block>,
+>;
+
+/// Destroys blocks that got hotter than their melting point, leaving pieces
+/// behind if their material decimates into something. Explosive blocks go
+/// off as they are destroyed.
+pub fn block_melt_system(
+    mut commands: Commands,
+    mut block_grid: ResMut<BlockGrid>,
+    mut level_stats: ResMut<LevelStats>,
+    materials: Materials,
+    query: MeltingBlocks,
+) {
+    let materials = match materials.get() {
+        Some(materials) => materials,
+        None => return,
+    };
+
+    for (
+        entity,
+        transform,
+        sprite_size,
+        block_size,
+        material_id,
+        heat,
+        thermal,
+        impact,
+        explosive,
+        level_block,
+    ) in query.iter()
+    {
+        if heat.0 < thermal.melting_point {
+            continue;
+        }
+
+        // remove the block
+        commands.entity(entity).despawn();
+        block_grid.remove(block_size, transform.translation, entity);
+        if level_block.is_some() {
+            level_stats.blocks_destroyed += 1;
+        }
+
+        // spawn the explosionToSpawn
+        let mut location = transform.translation;
+        // move up the Z
+        location[2] = 500.0;
+        let explosion = match explosive {
+            Some(explosive) => ExplosionToSpawn {
+                position: location,
+                radius: explosive.radius,
+                strength: explosive.strength,
+            },
+            None => ExplosionToSpawn::visual(location),
+        };
+
+        if materials.decimates_into(*material_id).is_some() {
+            let mut decimate = commands.spawn();
+            decimate
+                .insert(explosion)
+                .insert(BlockToDecimate(transform.translation))
+                .insert(*material_id)
+                .insert(sprite_size.clone())
+                .insert(LevelEntity);
+            if let Some(impact) = impact {
+                decimate.insert(*impact);
+            }
+        } else {
+            commands
+                .spawn()
+                .insert(explosion)
+                .insert(Unsupported(location))
+                .insert(LevelEntity);
+        }
+    }
+}
+
+type GlowingBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (&'static BlockHeat, &'static Thermal, &'static mut Sprite),
+    (With<Block>, Changed<BlockHeat>),
+>;
+
+/// Tints blocks from white towards a red glow as they near their melting point.
+pub fn block_glow_system(mut query: GlowingBlocks) {
+    for (heat, thermal, mut sprite) in query.iter_mut() {
+        let glow = ((heat.0 - AMBIENT_TEMPERATURE) / (thermal.melting_point - AMBIENT_TEMPERATURE))
+            .clamp(0., 1.);
+        sprite.color = Color::rgb(1., 1. - 0.6 * glow, 1. - glow);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::*;
+use bevy::prelude::*;
+
+/// Side of the square the actor collides with blocks with. A little smaller
+/// than a grid cell, so it fits through a one cell gap.
+pub const ACTOR_HITBOX_SIZE: f32 = GRID_WIDTH * 0.75;
+
+/// A path the actor moves along, in world space.
+pub struct Lane {
+    points: Vec<Vec2>,
+    /// Distance along the lane to each point.
+    distances: Vec<f32>,
+}
+
+impl Lane {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        let mut distances = Vec::with_capacity(points.len());
+        let mut total = 0.;
+        for (n, point) in points.iter().enumerate() {
+            if n > 0 {
+                total += point.distance(points[n - 1]);
+            }
+            distances.push(total);
+        }
+        Lane { points, distances }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.)
+    }
+
+    pub fn start(&self) -> Vec2 {
+        self.points[0]
+    }
+
+    pub fn end(&self) -> Vec2 {
+        self.points[self.points.len() - 1]
+    }
+
+    /// The point `distance` along the lane, stopping at either end.
+    pub fn point_at(&self, distance: f32) -> Vec2 {
+        let distance = distance.clamp(0., self.length());
+        for n in 1..self.points.len() {
+            if distance <= self.distances[n] {
+                let segment = self.distances[n] - self.distances[n - 1];
+                let t = (distance - self.distances[n - 1]) / segment.max(f32::EPSILON);
+                return self.points[n - 1].lerp(self.points[n], t);
+            }
+        }
+        self.end()
+    }
+
+    /// Distance along the lane to the point on it closest to `point`, and
+    /// how far off the lane `point` is.
+    pub fn closest(&self, point: Vec2) -> (f32, f32) {
+        let mut best = (0., self.start().distance(point));
+        for n in 1..self.points.len() {
+            let (a, b) = (self.points[n - 1], self.points[n]);
+            let segment = b - a;
+            let t = ((point - a).dot(segment) / segment.length_squared().max(f32::EPSILON))
+                .clamp(0., 1.);
+            let off = a.lerp(b, t).distance(point);
+            if off < best.1 {
+                best = (self.distances[n - 1] + t * segment.length(), off);
+            }
+        }
+        best
+    }
+}
+
+/// The lanes of `level` in world space, pulled in from the edges of the
+/// window so the actor always stays on screen.
+pub fn level_lanes(level: &Level, win_size: &WinSize) -> Vec<Lane> {
+    let half_window = Vec2::new(win_size.w, win_size.h) / 2. - ACTOR_HITBOX_SIZE / 2.;
+    level
+        .lanes
+        .iter()
+        .map(|lane| {
+            Lane::new(
+                lane.iter()
+                    .map(|(x, y)| level.grid_to_world(*x, *y).clamp(-half_window, half_window))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Which lane the actor is on, and how far along it.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct OnLane {
+    pub lane: usize,
+    pub distance: f32,
+}
+
+impl OnLane {
+    /// The spot on any of `lanes` closest to `point`.
+    pub fn closest(lanes: &[Lane], point: Vec2) -> Self {
+        let mut best = (
+            OnLane {
+                lane: 0,
+                distance: 0.,
+            },
+            f32::INFINITY,
+        );
+        for (n, lane) in lanes.iter().enumerate() {
+            let (distance, off) = lane.closest(point);
+            if off < best.1 {
+                best = (OnLane { lane: n, distance }, off);
+            }
+        }
+        best.0
+    }
+
+    /// Moves `step` along the lane, onto the lane that carries on from its
+    /// start or end if there is one, and otherwise stops at the end.
+    pub fn advance(self, lanes: &[Lane], step: f32) -> Self {
+        let lane = &lanes[self.lane];
+        let distance = self.distance + step;
+        // empty lanes are skipped so two of them in one spot can't loop forever
+        let joined =
+            |other: &Lane, a: Vec2, b: Vec2| other.length() > 0. && a.distance(b) < TOUCH_TOLERANCE;
+
+        if distance > lane.length() {
+            let next = lanes
+                .iter()
+                .position(|other| joined(other, other.start(), lane.end()));
+            if let Some(next) = next.filter(|next| *next != self.lane) {
+                let on_next = OnLane {
+                    lane: next,
+                    distance: 0.,
+                };
+                return on_next.advance(lanes, distance - lane.length());
+            }
+        } else if distance < 0. {
+            let previous = lanes
+                .iter()
+                .position(|other| joined(other, other.end(), lane.start()));
+            if let Some(previous) = previous.filter(|previous| *previous != self.lane) {
+                let on_previous = OnLane {
+                    lane: previous,
+                    distance: lanes[previous].length(),
+                };
+                return on_previous.advance(lanes, distance);
+            }
+        }
+
+        OnLane {
+            lane: self.lane,
+            distance: distance.clamp(0., lane.length()),
+        }
+    }
+}
@@ -1,103 +1,575 @@
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::prelude::*;
 
 use crate::{
     components::{
-        Block, BlockSize, BlockToDecimate, DespawnedList, ExplosionToSpawn, Laser, SpriteSize,
-        Velocity, WinSize,
+        Absorption, Actor, Beam, BlastRadius, Block, BlockSize, DespawnedList, ExplosionToSpawn,
+        GameState, Impact, Laser, LaserPower, LevelEntity, PreviousPosition, SpriteSize, Velocity,
+        WinSize,
     },
-    BlockHeat, Unsupported,
+    ActorInput, Aim, BlockGrid, BlockHeat, GameClock, GameTick, LevelStats, Optics, Thermal,
+    TickStep, Weapon, WeaponKind, ACTOR_SPRITE_SIZE, GRID_WIDTH, TOUCH_TOLERANCE,
 };
 
+pub const LASER_SCALE: f32 = 1.0;
+
+/// How fast lasers travel, in pixels per second.
+pub const LASER_SPEED: f32 = 1500.;
+
+/// Energy a laser puts into the block it hits.
+pub const LASER_HEAT: f32 = 1000.;
+
+/// Energy a beam puts into the block it touches, per second.
+pub const BEAM_HEAT: f32 = 3000.;
+
+/// Most stretches a beam is traced through, going from block to block.
+pub const MAX_BEAM_STRETCHES: usize = 32;
+
+/// Moves lasers and lets them, or the beam, heat the blocks they hit.
+pub(crate) struct LaserPlugin;
+
+impl Plugin for LaserPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_update(GameState::Running)
+                .label(TickStep::Lasers)
+                .after(TickStep::Actor)
+                .with_system(despawned_list_clear_system)
+                .with_system(laser_move_system.after(despawned_list_clear_system))
+                .with_system(laser_hit_system.after(laser_move_system))
+                .with_system(laser_beam_system.after(laser_hit_system)),
+        );
+    }
+}
+
+/// Entities only go once the tick is over, so the list starts empty each tick.
+pub fn despawned_list_clear_system(mut despawned_list: ResMut<DespawnedList>) {
+    despawned_list.0.clear();
+}
+
 pub fn laser_move_system(
     mut commands: Commands,
-    mut query: Query<(Entity, &Velocity, &mut Transform), With<Laser>>,
+    mut despawned_list: ResMut<DespawnedList>,
+    clock: Res<GameClock>,
+    mut query: Query<(Entity, &Velocity, &mut Transform, &mut PreviousPosition), With<Laser>>,
     win_size: Res<WinSize>,
 ) {
-    for (entity, velocity, mut transform) in query.iter_mut() {
+    let delta = clock.delta_seconds();
+    for (entity, velocity, mut transform, mut previous) in query.iter_mut() {
+        previous.0 = transform.translation.truncate();
         let translation = &mut transform.translation;
-        translation.x += velocity.x * 5.0;
+        translation.x += velocity.x * delta;
+        translation.y += velocity.y * delta;
 
-        if translation.x >= win_size.w / 2.0 {
+        if translation.x.abs() >= win_size.w / 2.0 || translation.y.abs() >= win_size.h / 2.0 {
+            despawned_list.0.insert(entity);
             commands.entity(entity).despawn();
         }
     }
 }
 
+/// Direction a laser travels in; lasers are drawn and collide lengthways
+/// along it.
+fn laser_direction(velocity: &Velocity) -> Vec2 {
+    let direction = Vec2::new(velocity.x, velocity.y).normalize_or_zero();
+    if direction == Vec2::ZERO {
+        Vec2::X
+    } else {
+        direction
+    }
+}
+
+/// Lasers weaker than this fizzle out.
+pub const MIN_LASER_POWER: f32 = 0.05;
+
+/// Spawns a laser of the given length and thickness with its centre at
+/// `position`, drawn along `velocity`.
+pub fn spawn_laser(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    size: Vec2,
+    position: Vec2,
+    velocity: Vec2,
+    power: f32,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            transform: Transform {
+                scale: Vec3::new(LASER_SCALE, LASER_SCALE, 1.),
+                translation: position.extend(2.0),
+                rotation: Quat::from_rotation_z(velocity.y.atan2(velocity.x)),
+            },
+            ..Default::default()
+        })
+        .insert(Velocity {
+            x: velocity.x,
+            y: velocity.y,
+        })
+        .insert(SpriteSize(size))
+        .insert(PreviousPosition(position))
+        .insert(LaserPower(power))
+        .insert(Laser)
+        .insert(LevelEntity)
+        .id()
+}
+
+/// Outward normal of the side of a box that `point`, on its edge, lies on.
+fn box_side(point: Vec2, centre: Vec2, size: Vec2) -> Vec2 {
+    let offset = (point - centre) / size;
+    if offset.x.abs() >= offset.y.abs() {
+        Vec2::new(offset.x.signum(), 0.)
+    } else {
+        Vec2::new(0., offset.y.signum())
+    }
+}
+
+type LaserTargets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static SpriteSize,
+        &'static BlockSize,
+        &'static mut BlockHeat,
+        &'static Thermal,
+        &'static Absorption,
+        Option<&'static Optics>,
+    ),
+    With<Block>,
+>;
+
+type MovingLasers<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Transform,
+        &'static mut PreviousPosition,
+        &'static mut Velocity,
+        &'static mut LaserPower,
+        &'static SpriteSize,
+        Option<&'static BlastRadius>,
+        Option<&'static Handle<Image>>,
+    ),
+    (With<Laser>, Without<Block>),
+>;
+
+/// Sweeps each laser from where it was to where it is now and hits the first
+/// block along the way. Blocks the same distance away are taken left to
+/// right, then bottom to top, so the same shot always hits the same block.
+/// Blocks soak up as much of the laser's power as heat as their material
+/// absorbs and a laser with power left goes on through; mirrors, splitters
+/// and glass send it on from the point it hit, see `Optics`. Bombs go off on
+/// the first block they hit, see `BlastRadius`.
 pub fn laser_hit_system(
     mut commands: Commands,
-    mut despawned_list: ResMut<DespawnedList>,
-    mut laser_query: Query<(Entity, &Transform, &SpriteSize), With<Laser>>,
-    mut block_query: Query<
-        (Entity, &Transform, &SpriteSize, &BlockSize, &mut BlockHeat),
-        With<Block>,
-    >,
+    despawned_list: Res<DespawnedList>,
+    mut laser_query: MovingLasers,
+    block_grid: Res<BlockGrid>,
+    mut block_query: LaserTargets,
 ) {
-    let despawned = &mut despawned_list.0;
-    for (laser_entity, laser_transform, laser_sprite_size) in laser_query.iter_mut() {
-        if despawned.contains(&laser_entity) {
+    for (
+        laser_entity,
+        mut laser_transform,
+        mut previous,
+        mut velocity,
+        mut power,
+        laser_size,
+        blast,
+        texture,
+    ) in laser_query.iter_mut()
+    {
+        // gone off the screen
+        if despawned_list.0.contains(&laser_entity) {
             continue;
         }
-        for (block_entity, block_transform, block_sprite_size, block_size, mut block_heat) in
-            block_query.iter_mut()
-        {
-            if despawned.contains(&block_entity) || despawned.contains(&laser_entity) {
-                continue;
-            }
 
-            let collision = collide(
-                laser_transform.translation,
-                laser_sprite_size.0,
-                block_transform.translation,
-                block_sprite_size.0,
-            );
+        // the laser's centre line from its back before the move to its front after
+        let direction = laser_direction(&velocity);
+        let speed = Vec2::new(velocity.x, velocity.y).length();
+        let half_length = laser_size.0.x / 2.;
+        let from = previous.0 - direction * half_length;
+        let to = laser_transform.translation.truncate() + direction * half_length;
+        let length = (to - from).length();
+        // blocks are grown by the laser's thickness so a glancing shot still hits
+        let thickness = Vec2::splat(laser_size.0.y);
 
-            const LASER_HEAT: u32 = 1000;
-            const BLOCK_MAX_HEAT: u32 = 1001;
-            // perform collision
-            if collision.is_some() {
-                block_heat.0 += LASER_HEAT;
-                if block_heat.0 >= BLOCK_MAX_HEAT {
-                    // remove the block
-                    despawned.insert(block_entity);
-                    commands.entity(block_entity).despawn();
-
-                    // spawn the explosionToSpawn
-                    let mut location = block_transform.translation;
-                    // move up the Z
-                    location[2] = 500.0;
-
-                    match block_size {
-                        BlockSize::Large(_) => {
-                            commands
-                                .spawn()
-                                .insert(ExplosionToSpawn(location))
-                                .insert(BlockToDecimate(block_transform.translation))
-                                .insert(block_size.clone());
-                        }
-                        BlockSize::Medium(_) => {
-                            commands
-                                .spawn()
-                                .insert(ExplosionToSpawn(location))
-                                .insert(Unsupported(location));
-                        }
-                        _ => panic!("Unsupported Block size"),
-                    }
-                }
+        let block_box = |entity| {
+            let (transform, sprite_size, ..) = block_query.get(entity).ok()?;
+            Some((transform.translation.truncate(), sprite_size.0))
+        };
+        let hit = first_hit(&block_grid, block_box, from, direction, length, thickness);
+        let (distance, centre, block_entity) = match hit {
+            Some(hit) => hit,
+            None => continue,
+        };
+        let (_, block_sprite_size, .., absorption, optics) = block_query.get(block_entity).unwrap();
+        let grown_box = (centre, block_sprite_size.0 + thickness);
+        let contact = from + direction * distance;
+        let optics = optics.copied().unwrap_or_default();
+
+        // what the block takes, and the lasers that carry on from it; bombs
+        // go off on whatever they hit first
+        let (absorbed, onward) = match blast {
+            Some(_) => (power.0, vec![]),
+            None => pass_block(
+                from,
+                direction,
+                distance,
+                grown_box,
+                optics,
+                absorption.0,
+                power.0,
+            ),
+        };
+
+        if let Some(BlastRadius(radius)) = blast {
+            commands
+                .spawn()
+                .insert(ExplosionToSpawn {
+                    position: contact.extend(500.0),
+                    radius: *radius,
+                    strength: LASER_HEAT * absorbed,
+                })
+                .insert(LevelEntity);
+        } else if absorbed > 0. {
+            let (block_transform, block_sprite_size, block_size, mut block_heat, block_thermal, ..) =
+                block_query.get_mut(block_entity).unwrap();
+            block_heat.add_energy(LASER_HEAT * absorbed, block_thermal);
 
-                // spawn the explosionToSpawn
-                let mut explosion_location = block_transform.translation;
-                // move up the Z
-                explosion_location[2] = 500.0;
+            // remember where the laser went in, for decimation
+            let block_min = block_transform.translation.truncate() - block_sprite_size.0 / 2.;
+            let block_max = block_transform.translation.truncate() + block_sprite_size.0 / 2.;
+            commands.entity(block_entity).insert(Impact {
+                point: contact.clamp(block_min, block_max),
+                direction,
+            });
 
-                commands
-                    .spawn()
-                    .insert(ExplosionToSpawn(explosion_location))
-                    .insert(block_size.clone());
+            // spawn the explosionToSpawn
+            let mut explosion_location = block_transform.translation;
+            // move up the Z
+            explosion_location[2] = 500.0;
+
+            commands
+                .spawn()
+                .insert(ExplosionToSpawn::visual(explosion_location))
+                .insert(block_size.clone())
+                .insert(LevelEntity);
+        }
 
+        // the laser itself carries on as the first onward beam, starting with
+        // its back on the block so the next sweep doesn't hit it again
+        let mut onward = onward
+            .into_iter()
+            .filter(|(_, _, onward_power)| *onward_power >= MIN_LASER_POWER);
+        match onward.next() {
+            Some((start, onward_direction, onward_power)) => {
+                let position = start + onward_direction * half_length;
+                laser_transform.translation = position.extend(laser_transform.translation.z);
+                laser_transform.rotation =
+                    Quat::from_rotation_z(onward_direction.y.atan2(onward_direction.x));
+                previous.0 = position;
+                velocity.x = onward_direction.x * speed;
+                velocity.y = onward_direction.y * speed;
+                power.0 = onward_power;
+            }
+            None => {
                 // remove the laser
-                despawned.insert(laser_entity);
                 commands.entity(laser_entity).despawn();
             }
         }
+        for (start, onward_direction, onward_power) in onward {
+            let position = start + onward_direction * half_length;
+            spawn_laser(
+                &mut commands,
+                texture.cloned().unwrap_or_default(),
+                laser_size.0,
+                position,
+                onward_direction * speed,
+                onward_power,
+            );
+        }
+    }
+}
+
+/// The first block a ray from `from` heading in `direction` runs into within
+/// `length`, as its distance, centre and entity, with `block_box` giving each
+/// block's centre and size. Blocks are grown by `thickness` so a glancing ray
+/// still hits, and looked up in the grid a cell's length of ray at a time.
+/// Equally near blocks go to the one with the smaller x, then y, so the
+/// result doesn't depend on query order.
+fn first_hit(
+    block_grid: &BlockGrid,
+    block_box: impl Fn(Entity) -> Option<(Vec2, Vec2)>,
+    from: Vec2,
+    direction: Vec2,
+    length: f32,
+    thickness: Vec2,
+) -> Option<(f32, Vec2, Entity)> {
+    let mut hit: Option<(f32, Vec2, Entity)> = None;
+    let mut start = 0.;
+    loop {
+        let end = (start + GRID_WIDTH).min(length);
+        let (a, b) = (from + direction * start, from + direction * end);
+        for entity in block_grid.candidates(((a + b) / 2.).extend(0.), (b - a).abs() + thickness) {
+            let (centre, size) = match block_box(entity) {
+                Some(block) => block,
+                None => continue,
+            };
+            let distance = match ray_through_box(from, direction, centre, size + thickness) {
+                // rays sent on from the edge of a block mustn't hit it again
+                Some((near, far)) if near < far && far > TOUCH_TOLERANCE && near <= length => {
+                    near.max(0.)
+                }
+                _ => continue,
+            };
+
+            let closer = hit.is_none_or(|(best, best_centre, _)| {
+                (distance, centre.x, centre.y) < (best, best_centre.x, best_centre.y)
+            });
+            if closer {
+                hit = Some((distance, centre, entity));
+            }
+        }
+
+        // nothing further along the ray can be nearer than a hit in this stretch
+        if end >= length || hit.is_some_and(|(distance, ..)| distance <= end) {
+            return hit;
+        }
+        start = end;
+    }
+}
+
+/// Distance along a ray to where it comes out of the far side of a box it
+/// goes through.
+fn ray_leaves_box(origin: Vec2, direction: Vec2, centre: Vec2, size: Vec2) -> f32 {
+    ray_through_box(origin, direction, centre, size).map_or(0., |(_, far)| far)
+}
+
+/// What a block takes of a ray of `power` that hits it `distance` along the
+/// ray from `from`, and the rays that carry on from it as start, direction
+/// and power, the way its optics and absorption say. `block_box` is the
+/// block's centre and size.
+fn pass_block(
+    from: Vec2,
+    direction: Vec2,
+    distance: f32,
+    block_box: (Vec2, Vec2),
+    optics: Optics,
+    absorption: f32,
+    power: f32,
+) -> (f32, Vec<(Vec2, Vec2, f32)>) {
+    let (centre, size) = block_box;
+    let contact = from + direction * distance;
+    // where the ray comes out of the far side, if it goes through
+    let exit = from + direction * ray_leaves_box(from, direction, centre, size);
+    let reflected = {
+        let normal = box_side(contact, centre, size);
+        direction - 2. * direction.dot(normal) * normal
+    };
+
+    match optics {
+        Optics::Absorb => {
+            let absorbed = power.min(absorption);
+            (absorbed, vec![(exit, direction, power - absorbed)])
+        }
+        Optics::Mirror => (0., vec![(contact, reflected, power)]),
+        Optics::Splitter => (
+            0.,
+            vec![
+                (contact, reflected, power / 2.),
+                (exit, direction, power / 2.),
+            ],
+        ),
+        Optics::Glass(transmission) => (
+            power * (1. - transmission),
+            vec![(exit, direction, power * transmission)],
+        ),
+    }
+}
+
+/// Distances along the line through `origin` in `direction` to where it
+/// enters and leaves the box, or `None` if it runs alongside it.
+fn ray_through_box(origin: Vec2, direction: Vec2, centre: Vec2, size: Vec2) -> Option<(f32, f32)> {
+    let (min, max) = (centre - size / 2., centre + size / 2.);
+    let (mut near, mut far) = (f32::NEG_INFINITY, f32::INFINITY);
+    for axis in 0..2 {
+        if direction[axis] == 0. {
+            // parallel to this pair of sides, so it has to start between them
+            if origin[axis] <= min[axis] || origin[axis] >= max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+    Some((near, far))
+}
+
+/// Distance along a ray from `origin` to the edge of the window.
+fn ray_to_window_edge(origin: Vec2, direction: Vec2, win_size: &WinSize) -> f32 {
+    let half = Vec2::new(win_size.w, win_size.h) / 2.;
+    let mut distance = f32::INFINITY;
+    for axis in 0..2 {
+        if direction[axis] > 0. {
+            distance = distance.min((half[axis] - origin[axis]) / direction[axis]);
+        } else if direction[axis] < 0. {
+            distance = distance.min((-half[axis] - origin[axis]) / direction[axis]);
+        }
+    }
+    distance.max(0.)
+}
+
+type BeamTargets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static SpriteSize,
+        &'static mut BlockHeat,
+        &'static Thermal,
+        &'static Absorption,
+        Option<&'static Optics>,
+    ),
+    (With<Block>, Without<Beam>),
+>;
+
+type Beams<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static mut Transform, &'static mut Sprite),
+    (With<Beam>, Without<Actor>, Without<Block>),
+>;
+
+/// While the actor holds a beam weapon and fire is held, casts a ray from it
+/// and heats the blocks it runs into for as long as the beam stays on them.
+/// Blocks take the beam's power the way they take a laser's, so it goes on
+/// through anything that doesn't soak all of it up and mirrors, splitters
+/// and glass send it on, see `laser_hit_system`. Each stretch of the beam is
+/// a sprite running to the next block, or to the edge of the window if
+/// nothing is in the way.
+#[allow(clippy::too_many_arguments)]
+pub fn laser_beam_system(
+    mut commands: Commands,
+    clock: Res<GameClock>,
+    asset_server: Res<AssetServer>,
+    win_size: Res<WinSize>,
+    input: Res<ActorInput>,
+    block_grid: Res<BlockGrid>,
+    mut level_stats: ResMut<LevelStats>,
+    mut actor_query: Query<(&Transform, &Aim, &mut Weapon), With<Actor>>,
+    mut beam_query: Beams,
+    mut block_query: BeamTargets,
+) {
+    // a beam already on keeps going when the last of the ammo is used up
+    let actor = actor_query.get_single_mut().ok().filter(|(_, _, weapon)| {
+        weapon.spec().kind == WeaponKind::Beam
+            && input.firing
+            && (weapon.ready() || !beam_query.is_empty())
+    });
+    let (actor_transform, aim, mut weapon) = match actor {
+        Some(actor) => actor,
+        None => {
+            for (entity, ..) in beam_query.iter() {
+                commands.entity(entity).despawn();
+            }
+            return;
+        }
+    };
+
+    let direction = aim.direction();
+    let origin = actor_transform.translation.truncate() + direction * ACTOR_SPRITE_SIZE.0 / 2.;
+    // each stretch of the beam as its start, direction and length
+    let mut stretches: Vec<(Vec2, Vec2, f32)> = Vec::new();
+    let mut rays = vec![(origin, direction, weapon.spec().power)];
+    while let Some((from, direction, power)) = rays.pop() {
+        // mirrors facing each other would otherwise bounce it forever
+        if stretches.len() >= MAX_BEAM_STRETCHES {
+            break;
+        }
+        let length = ray_to_window_edge(from, direction, &win_size);
+        let block_box = |entity| {
+            let (transform, sprite_size, ..) = block_query.get(entity).ok()?;
+            Some((transform.translation.truncate(), sprite_size.0))
+        };
+        let (distance, centre, entity) =
+            match first_hit(&block_grid, block_box, from, direction, length, Vec2::ZERO) {
+                Some(hit) => hit,
+                None => {
+                    stretches.push((from, direction, length));
+                    continue;
+                }
+            };
+        stretches.push((from, direction, distance));
+
+        let (_, sprite_size, mut block_heat, thermal, absorption, optics) =
+            block_query.get_mut(entity).unwrap();
+        let optics = optics.copied().unwrap_or_default();
+        let block_box = (centre, sprite_size.0);
+        let (absorbed, onward) = pass_block(
+            from,
+            direction,
+            distance,
+            block_box,
+            optics,
+            absorption.0,
+            power,
+        );
+        if absorbed > 0. {
+            block_heat.add_energy(BEAM_HEAT * absorbed * clock.delta_seconds(), thermal);
+            commands.entity(entity).insert(Impact {
+                point: from + direction * distance,
+                direction,
+            });
+        }
+        rays.extend(
+            onward
+                .into_iter()
+                .filter(|(_, _, onward_power)| *onward_power >= MIN_LASER_POWER),
+        );
+    }
+
+    let width = weapon.spec().size.1;
+    let beams: Vec<Entity> = beam_query.iter().map(|(entity, ..)| entity).collect();
+    let switched_on = beams.is_empty();
+    let stretch_count = stretches.len();
+    for (n, (from, direction, length)) in stretches.into_iter().enumerate() {
+        let transform = Transform {
+            translation: (from + direction * length / 2.).extend(2.0),
+            rotation: Quat::from_rotation_z(direction.y.atan2(direction.x)),
+            ..Default::default()
+        };
+        let size = Vec2::new(length, width);
+        if let Some(&entity) = beams.get(n) {
+            let (_, mut beam_transform, mut sprite) = beam_query.get_mut(entity).unwrap();
+            *beam_transform = transform;
+            sprite.custom_size = Some(size);
+            continue;
+        }
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: asset_server.load(weapon.spec().sprite.as_str()),
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform,
+                ..Default::default()
+            })
+            .insert(Beam)
+            .insert(LevelEntity);
+    }
+    // stretches the beam no longer has, e.g. when a mirror moved out of it
+    for entity in beams.into_iter().skip(stretch_count) {
+        commands.entity(entity).despawn();
+    }
+
+    if switched_on {
+        weapon.use_shot();
+        level_stats.shots += 1;
     }
 }
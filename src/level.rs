@@ -0,0 +1,546 @@
+use crate::*;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+
+/// Tiles that leave their cell empty, whatever the legend says.
+const EMPTY_TILES: [char; 2] = ['.', ' '];
+
+/// What has to happen for a level to count as complete.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LevelGoal {
+    #[default]
+    ClearBlocks,
+    /// Destroy this many of the blocks the level starts with. Pieces they
+    /// break into don't count.
+    DestroyBlocks(u32),
+}
+
+/// On-disk shape of a level, before the tile rows are validated.
+#[derive(Deserialize)]
+struct LevelFile {
+    name: String,
+    par_shots: u32,
+    width: usize,
+    height: usize,
+    actor_start: (usize, usize),
+    #[serde(default)]
+    goal: LevelGoal,
+    #[serde(default)]
+    adhesion: Option<f32>,
+    #[serde(default)]
+    lanes: Vec<Vec<(usize, usize)>>,
+    #[serde(default)]
+    weapons: Vec<String>,
+    /// Tiles that don't use a material's own glyph, mapped to material names.
+    #[serde(default)]
+    legend: HashMap<char, String>,
+    rows: Vec<String>,
+}
+
+#[derive(TypeUuid)]
+#[uuid = "6f3c1e0a-7d52-4b8e-9a61-2c4f0b9d83e7"]
+pub struct Level {
+    /// Asset path the level was loaded from; empty for levels built straight
+    /// from a string.
+    pub id: String,
+    pub name: String,
+    pub par_shots: u32,
+    pub width: usize,
+    pub height: usize,
+    pub actor_start: (usize, usize),
+    pub goal: LevelGoal,
+    /// How far, in grid cells, blocks can hang sideways off a block that
+    /// rests on something. `None` means there is no limit.
+    pub adhesion: Option<f32>,
+    /// Paths the actor moves along, as grid cells joined by straight lines.
+    /// A lane that starts where another ends carries on from it. Without any,
+    /// the actor moves up and down the column it starts in.
+    pub lanes: Vec<Vec<(usize, usize)>>,
+    /// Names of the weapons the actor gets, in the order it switches through
+    /// them. Without any, it gets every weapon in the arsenal.
+    pub weapons: Vec<String>,
+    /// Material name of every tile, row by row.
+    tiles: Vec<Option<String>>,
+}
+
+impl Level {
+    pub fn from_ron(source: &str, materials: &MaterialRegistry) -> Result<Self, DataError> {
+        let file: LevelFile = ron::from_str(source)?;
+        for name in file.legend.values() {
+            if materials.id(name).is_none() {
+                return Err(DataError::at_quoted(
+                    source,
+                    name,
+                    format!("material \"{}\" is not defined", name),
+                ));
+            }
+        }
+        let row_positions = locate_rows(source, &file.rows);
+
+        if file.rows.len() != file.height {
+            let (line, column) = row_positions.last().copied().unwrap_or((1, 1));
+            return Err(DataError {
+                line,
+                column,
+                message: format!("expected {} rows, found {}", file.height, file.rows.len()),
+            });
+        }
+
+        let mut tiles = Vec::with_capacity(file.width * file.height);
+        for (row, (line, column)) in file.rows.iter().zip(row_positions) {
+            let row_len = row.chars().count();
+            if row_len != file.width {
+                return Err(DataError {
+                    line,
+                    column: column + row_len.min(file.width),
+                    message: format!("expected {} tiles in row, found {}", file.width, row_len),
+                });
+            }
+
+            for (n, tile) in row.chars().enumerate() {
+                if EMPTY_TILES.contains(&tile) {
+                    tiles.push(None);
+                    continue;
+                }
+                let material = file.legend.get(&tile).cloned().or_else(|| {
+                    materials
+                        .by_glyph(tile)
+                        .map(|material| material.name.clone())
+                });
+                match material {
+                    Some(name) => tiles.push(Some(name)),
+                    None => {
+                        return Err(DataError {
+                            line,
+                            column: column + n,
+                            message: format!("tile '{}' is not in the legend or a material", tile),
+                        })
+                    }
+                }
+            }
+        }
+
+        let (start_x, start_y) = file.actor_start;
+        if start_x >= file.width || start_y >= file.height {
+            return Err(DataError {
+                line: 1,
+                column: 1,
+                message: format!(
+                    "actor_start {:?} is outside the {}x{} level",
+                    file.actor_start, file.width, file.height
+                ),
+            });
+        }
+
+        for lane in file.lanes.iter() {
+            let outside = lane
+                .iter()
+                .find(|(x, y)| *x >= file.width || *y >= file.height);
+            if lane.is_empty() || outside.is_some() {
+                let offset = source.find("lanes").unwrap_or(0);
+                let (line, column) = line_and_column(source, offset);
+                let message = match outside {
+                    Some(point) => format!(
+                        "lane point {:?} is outside the {}x{} level",
+                        point, file.width, file.height
+                    ),
+                    None => "lane has no points".to_string(),
+                };
+                return Err(DataError {
+                    line,
+                    column,
+                    message,
+                });
+            }
+        }
+        let lanes = if file.lanes.is_empty() {
+            vec![vec![(start_x, 0), (start_x, file.height - 1)]]
+        } else {
+            file.lanes
+        };
+
+        Ok(Level {
+            id: String::new(),
+            name: file.name,
+            par_shots: file.par_shots,
+            width: file.width,
+            height: file.height,
+            actor_start: file.actor_start,
+            goal: file.goal,
+            adhesion: file.adhesion,
+            lanes,
+            weapons: file.weapons,
+            tiles,
+        })
+    }
+
+    /// Checks the level only asks for weapons the arsenal has.
+    pub fn check_weapons(&self, source: &str, arsenal: &Arsenal) -> Result<(), DataError> {
+        match self.weapons.iter().find(|name| arsenal.get(name).is_none()) {
+            Some(name) => Err(DataError::at_quoted(
+                source,
+                name,
+                format!("weapon \"{}\" is not defined", name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Grid coordinates and material name of every block, row by row.
+    pub fn blocks(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        self.tiles.iter().enumerate().filter_map(|(n, tile)| {
+            tile.as_deref()
+                .map(|material| (n % self.width, n / self.width, material))
+        })
+    }
+
+    /// Bottom edge of the level in world space.
+    pub fn floor(&self) -> f32 {
+        -(self.height as f32) * GRID_WIDTH / 2.
+    }
+
+    /// Centre of a grid cell in world space, with the level centred on the origin.
+    pub fn grid_to_world(&self, x: usize, y: usize) -> Vec2 {
+        Vec2::new(
+            x as f32 * GRID_WIDTH - self.width as f32 * GRID_WIDTH / 2. + GRID_WIDTH / 2.,
+            -(y as f32 * GRID_WIDTH - self.height as f32 * GRID_WIDTH / 2. + GRID_WIDTH / 2.),
+        )
+    }
+}
+
+/// Line and column of the first tile of each row, found by walking the
+/// quoted rows in order after the `rows` key. Only used for error reports.
+fn locate_rows(source: &str, rows: &[String]) -> Vec<(usize, usize)> {
+    let mut offset = field_keys(source, "rows").first().copied().unwrap_or(0);
+    rows.iter()
+        .map(|row| {
+            let quoted = format!("\"{}\"", row);
+            if let Some(found) = source[offset..].find(&quoted) {
+                offset += found + 1;
+            }
+            line_and_column(source, offset)
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            // tiles and weapons are checked against the materials and the
+            // arsenal, so read them alongside the level
+            let material_bytes = load_context.read_asset_bytes(MATERIALS_FILE).await?;
+            let materials = MaterialRegistry::from_ron(std::str::from_utf8(&material_bytes)?)
+                .map_err(|err| anyhow::anyhow!("{}:{}", MATERIALS_FILE, err))?;
+            let weapon_bytes = load_context.read_asset_bytes(WEAPONS_FILE).await?;
+            let arsenal = Arsenal::from_ron(std::str::from_utf8(&weapon_bytes)?)
+                .map_err(|err| anyhow::anyhow!("{}:{}", WEAPONS_FILE, err))?;
+
+            let source = std::str::from_utf8(bytes)?;
+            let mut level = Level::from_ron(source, &materials)
+                .and_then(|level| level.check_weapons(source, &arsenal).map(|_| level))
+                .map_err(|err| anyhow::anyhow!("{}:{}", load_context.path().display(), err))?;
+            level.id = load_context.path().to_string_lossy().replace('\\', "/");
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+pub struct LevelHandle(pub Handle<Level>);
+
+impl AssetHandle for LevelHandle {
+    type Asset = Level;
+
+    fn handle(&self) -> &Handle<Level> {
+        &self.0
+    }
+}
+
+/// The level being played, for systems that only need to read it.
+pub type CurrentLevel<'w, 's> = Loaded<'w, 's, LevelHandle>;
+
+/// Starts loading the campaign's current level, or the one a replay was
+/// recorded on, and waits for it, the materials and the weapons to arrive.
+#[allow(clippy::too_many_arguments)]
+pub fn level_loaded_system(
+    asset_server: Res<AssetServer>,
+    campaigns: Res<Assets<Campaign>>,
+    progress: Res<CampaignProgress>,
+    replay_player: Option<Res<ReplayPlayer>>,
+    levels: Res<Assets<Level>>,
+    materials: Materials,
+    weapons: Weapons,
+    mut level_handle: ResMut<LevelHandle>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(player) = replay_player {
+        level_handle.0 = asset_server.load(player.replay.level.as_str());
+    } else {
+        let campaign = match campaigns.get(&progress.campaign) {
+            Some(campaign) => campaign,
+            None => return,
+        };
+        level_handle.0 = asset_server.load(campaign.levels[progress.level].as_str());
+    }
+
+    if materials.get().is_none() || weapons.get().is_none() {
+        return;
+    }
+    if let Some(level) = levels.get(&level_handle.0) {
+        info!(
+            "level \"{}\" loaded, par {} shots",
+            level.name, level.par_shots
+        );
+        state.set(GameState::GameSetup).unwrap();
+    }
+}
+
+/// Rebuilds the blocks when the level or materials file changes on disk.
+#[allow(clippy::too_many_arguments)]
+pub fn level_reload_system(
+    mut commands: Commands,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    mut material_events: EventReader<AssetEvent<MaterialRegistry>>,
+    level_handle: Res<LevelHandle>,
+    materials_handle: Res<MaterialsHandle>,
+    mut block_grid: ResMut<BlockGrid>,
+    block_query: Query<Entity, With<Block>>,
+    decimate_query: Query<Entity, With<BlockToDecimate>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let level_modified = level_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => *handle == level_handle.0,
+        _ => false,
+    });
+    let materials_modified = material_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => *handle == materials_handle.0,
+        _ => false,
+    });
+
+    if level_modified || materials_modified {
+        for entity in block_query.iter().chain(decimate_query.iter()) {
+            commands.entity(entity).despawn();
+        }
+        block_grid.clear();
+        state.set(GameState::LevelReload).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATERIALS: &str = include_str!("../assets/blocks.materials.ron");
+
+    fn parse(source: &str) -> Result<Level, DataError> {
+        Level::from_ron(source, &MaterialRegistry::from_ron(MATERIALS).unwrap())
+    }
+
+    fn parse_err(source: &str) -> DataError {
+        match parse(source) {
+            Ok(_) => panic!("level should not parse"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn reads_a_valid_level() {
+        let level = parse(
+            r##"(
+    name: "Tiny",
+    par_shots: 1,
+    width: 3,
+    height: 2,
+    actor_start: (0, 1),
+    rows: [
+        "..#",
+        "S.#",
+    ],
+)"##,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+        let blocks: Vec<_> = level.blocks().collect();
+        assert_eq!(
+            blocks,
+            vec![(2, 0, "metal"), (0, 1, "support"), (2, 1, "metal")]
+        );
+        assert_eq!(level.goal, LevelGoal::ClearBlocks);
+    }
+
+    #[test]
+    fn reports_a_short_row_where_it_ends() {
+        let err = parse_err(
+            r##"(
+    name: "Ragged",
+    par_shots: 1,
+    width: 3,
+    height: 2,
+    actor_start: (0, 1),
+    rows: [
+        "...",
+        "#.",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "expected 3 tiles in row, found 2");
+        assert_eq!((err.line, err.column), (9, 12));
+    }
+
+    #[test]
+    fn reports_a_long_row_after_the_last_tile_that_fits() {
+        let err = parse_err(
+            r##"(
+    name: "Ragged",
+    par_shots: 1,
+    width: 3,
+    height: 2,
+    actor_start: (0, 1),
+    rows: [
+        "....",
+        "...",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "expected 3 tiles in row, found 4");
+        assert_eq!((err.line, err.column), (8, 13));
+    }
+
+    #[test]
+    fn reports_a_missing_row() {
+        let err = parse_err(
+            r##"(
+    name: "Short",
+    par_shots: 1,
+    width: 3,
+    height: 3,
+    actor_start: (0, 1),
+    rows: [
+        "...",
+        "...",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "expected 3 rows, found 2");
+        assert_eq!((err.line, err.column), (9, 10));
+    }
+
+    #[test]
+    fn finds_the_rows_after_their_key() {
+        // "rows" in the name and a row's text before the key don't count
+        let err = parse_err(
+            r##"(
+    name: "Arrows",
+    par_shots: 1,
+    width: 3,
+    height: 3,
+    actor_start: (0, 1),
+    weapons: ["..."],
+    rows: [
+        "...",
+        "...",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "expected 3 rows, found 2");
+        assert_eq!((err.line, err.column), (10, 10));
+    }
+
+    #[test]
+    fn reports_an_unknown_tile_at_its_column() {
+        let err = parse_err(
+            r##"(
+    name: "Unknown",
+    par_shots: 1,
+    width: 3,
+    height: 2,
+    actor_start: (0, 1),
+    rows: [
+        "...",
+        "#?#",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "tile '?' is not in the legend or a material");
+        assert_eq!((err.line, err.column), (9, 11));
+    }
+
+    #[test]
+    fn reports_a_legend_entry_for_a_missing_material() {
+        let err = parse_err(
+            r##"(
+    name: "Legend",
+    par_shots: 1,
+    width: 1,
+    height: 1,
+    actor_start: (0, 0),
+    legend: {
+        'x': "unobtainium",
+    },
+    rows: [
+        "x",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "material \"unobtainium\" is not defined");
+        assert_eq!((err.line, err.column), (8, 15));
+    }
+
+    #[test]
+    fn reports_a_missing_field() {
+        let err = parse_err(
+            r##"(
+    name: "Missing",
+    width: 1,
+    height: 1,
+    actor_start: (0, 0),
+    rows: [
+        ".",
+    ],
+)"##,
+        );
+
+        assert!(err.message.contains("par_shots"), "{}", err.message);
+        assert_eq!((err.line, err.column), (1, 1));
+    }
+
+    #[test]
+    fn reports_an_actor_start_outside_the_level() {
+        let err = parse_err(
+            r##"(
+    name: "Outside",
+    par_shots: 1,
+    width: 1,
+    height: 1,
+    actor_start: (1, 0),
+    rows: [
+        ".",
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "actor_start (1, 0) is outside the 1x1 level");
+    }
+}
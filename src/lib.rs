@@ -0,0 +1,207 @@
+mod action;
+mod actor;
+mod animation;
+mod block;
+mod campaign;
+mod components;
+mod data;
+mod explosion;
+mod falling;
+mod grid;
+#[cfg(feature = "harness")]
+pub mod harness;
+mod heat;
+mod lane;
+mod laser;
+mod level;
+mod material;
+mod replay;
+mod support;
+mod weapon;
+pub use action::*;
+pub use actor::*;
+pub use animation::*;
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+pub use block::*;
+pub use campaign::*;
+pub use components::*;
+pub use data::*;
+pub use explosion::*;
+pub use falling::*;
+pub use grid::*;
+pub use heat::*;
+pub use lane::*;
+pub use laser::*;
+pub use level::*;
+pub use material::*;
+pub use replay::*;
+pub use support::*;
+pub use weapon::*;
+
+pub const SCREEN_WIDTH: f32 = 1024.0;
+pub const SCREEN_HEIGHT: f32 = 1024.0;
+
+/// Seconds of game time every tick simulates.
+pub const TIMESTEP: f32 = 1. / 60.;
+/// Most real time a single update catches up on, so a long stall doesn't
+/// turn into a burst of ticks.
+const MAX_CATCH_UP: f64 = 0.25;
+
+/// Stage the gameplay runs in, once per fixed tick of `TIMESTEP` seconds.
+/// Its systems run one at a time in a fixed order, so the same level and the
+/// same input always give the same result.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub struct GameTick;
+
+/// Parts of a tick, in the order they run. Each plugin puts its `GameTick`
+/// systems in one step and orders them within it; Bevy would otherwise pick
+/// a different order for unrelated systems every time the game starts.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, SystemLabel)]
+pub enum TickStep {
+    Input,
+    Actor,
+    Lasers,
+    Blocks,
+    Explosions,
+    Animation,
+    Level,
+}
+
+/// The whole destruction sandbox: campaign and level flow plus the action,
+/// block, laser, weapon, actor, explosion, animation and replay plugins. All
+/// but the action plugin run in its `GameTick` stage and game states and
+/// read the resources it sets up, so they can only be added through it. It
+/// needs the asset server but not a window, a GPU or an input device, so it
+/// also runs headless on `MinimalPlugins`, where keys can be pressed on the
+/// `Input<KeyCode>` resource directly. The app provides `WinSize`.
+pub struct SpritexelPlugin;
+
+impl Plugin for SpritexelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_stage_after(
+            CoreStage::Update,
+            GameTick,
+            SystemStage::single_threaded().with_run_criteria(game_tick_criteria),
+        )
+        .add_state_to_stage(GameTick, GameState::StartUp)
+        .add_asset::<Level>()
+        .init_asset_loader::<LevelLoader>()
+        .add_asset::<Campaign>()
+        .init_asset_loader::<RonLoader<Campaign>>()
+        .init_resource::<GameClock>()
+        .insert_resource(DespawnedList(Default::default()))
+        .add_plugin(ActionPlugin)
+        .add_plugin(BlockPlugin)
+        .add_plugin(LaserPlugin)
+        .add_plugin(WeaponPlugin)
+        .add_plugin(ActorPlugin)
+        .add_plugin(ExplosionPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::StartUp)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(campaign_setup_system)
+                .with_system(game_setup_system.after(campaign_setup_system)),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_update(GameState::LevelLoading)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(level_loaded_system),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::GameSetup)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(game_clock_setup_system)
+                .with_system(level_stats_setup_system.after(game_clock_setup_system))
+                .with_system(game_run_system.after(level_stats_setup_system)),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_update(GameState::Running)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(level_reload_system)
+                .with_system(level_complete_check_system.after(level_reload_system)),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::LevelReload)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(game_run_system),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::LevelComplete)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(level_complete_system),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::CampaignComplete)
+                .label(TickStep::Level)
+                .after(TickStep::Animation)
+                .with_system(campaign_complete_system),
+        );
+    }
+}
+
+/// Counts fixed ticks and holds the real time that hasn't been simulated yet.
+#[derive(Default)]
+pub struct GameClock {
+    /// Ticks run since the level was set up.
+    pub tick: u64,
+    /// Run exactly one tick per update whatever `Time` says, which lets the
+    /// headless harness step the game.
+    pub step_every_update: bool,
+    accumulator: f64,
+    looping: bool,
+}
+
+impl GameClock {
+    pub fn delta_seconds(&self) -> f32 {
+        TIMESTEP
+    }
+}
+
+/// Runs the `GameTick` stage once for every `TIMESTEP` of real time that
+/// passed, carrying the remainder over to the next update.
+pub fn game_tick_criteria(time: Res<Time>, mut clock: ResMut<GameClock>) -> ShouldRun {
+    if !clock.looping {
+        clock.accumulator = if clock.step_every_update {
+            TIMESTEP as f64
+        } else {
+            (clock.accumulator + time.delta_seconds_f64()).min(MAX_CATCH_UP)
+        };
+    }
+
+    if clock.accumulator >= TIMESTEP as f64 {
+        clock.accumulator -= TIMESTEP as f64;
+        clock.tick += 1;
+        clock.looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        clock.looping = false;
+        ShouldRun::No
+    }
+}
+
+pub fn game_clock_setup_system(mut clock: ResMut<GameClock>) {
+    clock.tick = 0;
+}
+
+pub fn game_run_system(mut state: ResMut<State<GameState>>) {
+    state.set(GameState::Running).unwrap();
+}
+
+pub fn game_setup_system(mut state: ResMut<State<GameState>>) {
+    state.set(GameState::LevelLoading).unwrap();
+}
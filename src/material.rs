@@ -0,0 +1,393 @@
+use crate::*;
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use serde::Deserialize;
+
+pub const MATERIALS_FILE: &str = "blocks.materials.ron";
+
+/// Which size of block a material is made into.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockTier {
+    Small,
+    Medium,
+    Large,
+}
+
+impl BlockTier {
+    pub fn block_size(self) -> BlockSize {
+        match self {
+            BlockTier::Small => BlockSize::Small(BLOCK_SMALL_SIZE as u8),
+            BlockTier::Medium => BlockSize::Medium(BLOCK_MEDIUM_SIZE as u8),
+            BlockTier::Large => BlockSize::Large(GRID_WIDTH as u8),
+        }
+    }
+
+    pub fn sprite_size(self) -> SpriteSize {
+        SpriteSize(Vec2::splat(self.block_size().side()))
+    }
+}
+
+/// What a block does to a laser that hits it.
+#[derive(Deserialize, Component, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Optics {
+    /// Takes the laser's power as heat, up to the material's `absorption`,
+    /// and lets whatever is left carry on through.
+    #[default]
+    Absorb,
+    /// Bounces the laser off the side it hit.
+    Mirror,
+    /// Lets half of the laser's power through and bounces the other half.
+    Splitter,
+    /// Lets the laser through, keeping this fraction of its power and taking
+    /// the rest as heat.
+    Glass(f32),
+}
+
+/// How a block goes off when it is destroyed.
+#[derive(Deserialize, Component, Clone, Copy, PartialEq, Debug)]
+pub struct Explosive {
+    pub radius: f32,
+    /// Energy it puts into blocks right next to it, see `ExplosionToSpawn`.
+    pub strength: f32,
+}
+
+fn default_density() -> f32 {
+    1.
+}
+
+fn default_absorption() -> f32 {
+    f32::INFINITY
+}
+
+fn default_pieces() -> u32 {
+    4
+}
+
+/// One kind of block, as described in the materials file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockMaterial {
+    pub name: String,
+    /// Tile that places this material in a level, unless the level's legend
+    /// says otherwise. Materials that only come from decimation have none.
+    #[serde(default)]
+    pub glyph: Option<char>,
+    pub sprite: String,
+    pub tier: BlockTier,
+    pub melting_point: f32,
+    pub heat_capacity: f32,
+    /// Zero for materials that don't pass heat on.
+    #[serde(default)]
+    pub conductivity: f32,
+    /// Material the pieces are made of when the block is destroyed. Blocks
+    /// without one are simply removed.
+    #[serde(default)]
+    pub decimates_into: Option<String>,
+    /// Pieces along each side when the block is decimated, so it splits
+    /// into `pieces * pieces` blocks.
+    #[serde(default = "default_pieces")]
+    pub pieces: u32,
+    #[serde(default)]
+    pub optics: Optics,
+    /// Most laser power, in full shots, a block soaks up from one hit before
+    /// the rest goes through it. Blocks without one stop every laser.
+    #[serde(default = "default_absorption")]
+    pub absorption: f32,
+    /// Holds up whatever is connected to it, like the floor does, and never
+    /// falls itself.
+    #[serde(default)]
+    pub support: bool,
+    /// Relative weight; denser blocks can't hang as far off their neighbours.
+    #[serde(default = "default_density")]
+    pub density: f32,
+    /// Blows up when destroyed, heating the blocks around it.
+    #[serde(default)]
+    pub explosive: Option<Explosive>,
+}
+
+impl BlockMaterial {
+    pub fn thermal(&self) -> Thermal {
+        Thermal {
+            capacity: self.heat_capacity,
+            melting_point: self.melting_point,
+            conductivity: self.conductivity,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialsFile {
+    materials: Vec<BlockMaterial>,
+}
+
+/// Every block material, looked up by name or by the `MaterialId` stored on
+/// a block.
+#[derive(TypeUuid)]
+#[uuid = "c4e2a9f1-5b37-4d08-8e6c-91a3f7d2b540"]
+pub struct MaterialRegistry {
+    materials: Vec<BlockMaterial>,
+    by_name: HashMap<String, MaterialId>,
+}
+
+/// Index of a block's material in the `MaterialRegistry`.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaterialId(pub usize);
+
+impl RonAsset for MaterialRegistry {
+    const EXTENSIONS: &'static [&'static str] = &["materials.ron"];
+
+    fn from_ron(source: &str) -> Result<Self, DataError> {
+        let file: MaterialsFile = ron::from_str(source)?;
+
+        let mut by_name = HashMap::new();
+        let mut glyphs: HashMap<char, String> = HashMap::new();
+        for (n, material) in file.materials.iter().enumerate() {
+            if by_name
+                .insert(material.name.clone(), MaterialId(n))
+                .is_some()
+            {
+                return Err(DataError::at_name(
+                    source,
+                    &material.name,
+                    1,
+                    format!("material \"{}\" is defined twice", material.name),
+                ));
+            }
+            if let Some(glyph) = material.glyph {
+                if let Some(other) = glyphs.insert(glyph, material.name.clone()) {
+                    return Err(DataError::at_name(
+                        source,
+                        &material.name,
+                        0,
+                        format!("glyph '{}' is already used by \"{}\"", glyph, other),
+                    ));
+                }
+            }
+        }
+
+        for material in file.materials.iter() {
+            if material.heat_capacity <= 0. {
+                return Err(DataError::at_name(
+                    source,
+                    &material.name,
+                    0,
+                    format!(
+                        "material \"{}\" needs a positive heat capacity",
+                        material.name
+                    ),
+                ));
+            }
+            if material.melting_point <= AMBIENT_TEMPERATURE {
+                return Err(DataError::at_name(
+                    source,
+                    &material.name,
+                    0,
+                    format!(
+                        "material \"{}\" has to melt above the ambient {} degrees",
+                        material.name, AMBIENT_TEMPERATURE
+                    ),
+                ));
+            }
+            if material.absorption <= 0. {
+                return Err(DataError::at_name(
+                    source,
+                    &material.name,
+                    0,
+                    format!(
+                        "material \"{}\" has to absorb some laser power",
+                        material.name
+                    ),
+                ));
+            }
+            if let Some(explosive) = material.explosive {
+                if explosive.radius <= 0. || explosive.strength <= 0. {
+                    return Err(DataError::at_name(
+                        source,
+                        &material.name,
+                        0,
+                        format!(
+                            "material \"{}\" needs a positive explosive radius and strength",
+                            material.name
+                        ),
+                    ));
+                }
+            }
+            if let Optics::Glass(transmission) = material.optics {
+                if !(0. ..=1.).contains(&transmission) {
+                    return Err(DataError::at_name(
+                        source,
+                        &material.name,
+                        0,
+                        format!(
+                            "glass \"{}\" lets through {} of a laser, not between 0 and 1",
+                            material.name, transmission
+                        ),
+                    ));
+                }
+            }
+            if let Some(into) = &material.decimates_into {
+                let piece = match by_name.get(into) {
+                    Some(id) => &file.materials[id.0],
+                    None => {
+                        return Err(DataError::at_quoted(
+                            source,
+                            into,
+                            format!("material \"{}\" is not defined", into),
+                        ))
+                    }
+                };
+                // the pieces have to tile the block exactly
+                let side = material.tier.block_size().side();
+                let piece_side = piece.tier.block_size().side();
+                if piece_side * material.pieces as f32 != side {
+                    return Err(DataError::at_quoted(
+                        source,
+                        into,
+                        format!(
+                            "{} pieces of \"{}\" don't fill the {} pixel side of \"{}\"",
+                            material.pieces, into, side, material.name
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(MaterialRegistry {
+            materials: file.materials,
+            by_name,
+        })
+    }
+}
+
+impl MaterialRegistry {
+    pub fn get(&self, id: MaterialId) -> &BlockMaterial {
+        &self.materials[id.0]
+    }
+
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Material a level tile stands for when its legend doesn't mention it.
+    pub fn by_glyph(&self, glyph: char) -> Option<&BlockMaterial> {
+        self.materials
+            .iter()
+            .find(|material| material.glyph == Some(glyph))
+    }
+
+    /// Material the pieces of a destroyed block are made of, if any.
+    pub fn decimates_into(&self, id: MaterialId) -> Option<MaterialId> {
+        self.get(id)
+            .decimates_into
+            .as_deref()
+            .and_then(|name| self.id(name))
+    }
+}
+
+pub struct MaterialsHandle(pub Handle<MaterialRegistry>);
+
+impl AssetHandle for MaterialsHandle {
+    type Asset = MaterialRegistry;
+
+    fn handle(&self) -> &Handle<MaterialRegistry> {
+        &self.0
+    }
+}
+
+/// The loaded material registry, for systems that only need to read it.
+pub type Materials<'w, 's> = Loaded<'w, 's, MaterialsHandle>;
+
+pub fn material_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MaterialsHandle(asset_server.load(MATERIALS_FILE)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(source: &str) -> DataError {
+        match MaterialRegistry::from_ron(source) {
+            Ok(_) => panic!("materials should not parse"),
+            Err(err) => err,
+        }
+    }
+
+    /// A materials file holding just `fields` as one material called "wax".
+    fn wax(fields: &str) -> String {
+        format!(
+            r##"(
+    materials: [
+        (
+            name: "wax",
+            sprite: "wax.png",
+            tier: Large,
+            {}
+        ),
+    ],
+)"##,
+            fields
+        )
+    }
+
+    #[test]
+    fn reports_a_material_defined_twice_where_it_comes_again() {
+        let err = parse_err(
+            r##"(
+    materials: [
+        (
+            name: "candle",
+            sprite: "candle.png",
+            tier: Large,
+            melting_point: 60.0,
+            heat_capacity: 1.0,
+            conductivity: 0.1,
+            decimates_into: Some("wax"),
+        ),
+        (
+            name: "wax",
+            sprite: "wax.png",
+            tier: Large,
+            melting_point: 60.0,
+            heat_capacity: 1.0,
+            conductivity: 0.1,
+        ),
+        (
+            name: "wax",
+            sprite: "wax.png",
+            tier: Large,
+            melting_point: 60.0,
+            heat_capacity: 1.0,
+            conductivity: 0.1,
+        ),
+    ],
+)"##,
+        );
+
+        assert_eq!(err.message, "material \"wax\" is defined twice");
+        assert_eq!((err.line, err.column), (21, 20));
+    }
+
+    #[test]
+    fn reports_a_heat_capacity_that_is_not_positive() {
+        let err = parse_err(&wax(
+            "melting_point: 60.0, heat_capacity: 0.0, conductivity: 0.1,",
+        ));
+
+        assert_eq!(
+            err.message,
+            "material \"wax\" needs a positive heat capacity"
+        );
+        assert_eq!((err.line, err.column), (4, 20));
+    }
+
+    #[test]
+    fn reports_a_melting_point_at_or_below_ambient() {
+        let err = parse_err(&wax(
+            "melting_point: 20.0, heat_capacity: 1.0, conductivity: 0.1,",
+        ));
+
+        assert_eq!(
+            err.message,
+            "material \"wax\" has to melt above the ambient 20 degrees"
+        );
+        assert_eq!((err.line, err.column), (4, 20));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::*;
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Everything the actor was told to do during one level, tick by tick.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    /// Asset path of the level, e.g. `levels/01.level.ron`.
+    pub level: String,
+    /// Ticks from the start of the level until recording stopped.
+    pub ticks: u64,
+    /// The actor's input on every tick where it changed.
+    pub inputs: Vec<(u64, ActorInput)>,
+}
+
+impl Replay {
+    pub fn from_ron(source: &str) -> Result<Self, DataError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, Default::default()).unwrap()
+    }
+}
+
+/// Records the actor's input while it is present. A recording covers a single
+/// level: it is kept in memory and saved to `path` when that level is
+/// complete, which also ends it, or when the app exits first.
+pub struct ReplayRecorder {
+    pub path: Option<PathBuf>,
+    pub replay: Replay,
+    last: ActorInput,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: Option<PathBuf>, level: &str) -> Self {
+        ReplayRecorder {
+            path,
+            replay: Replay {
+                level: level.to_string(),
+                ..Default::default()
+            },
+            last: ActorInput::default(),
+        }
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(err) = std::fs::write(path, self.replay.to_ron()) {
+                warn!("could not save replay to {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Feeds a recorded replay to the actor instead of the keyboard. The campaign
+/// is over once the replayed level is complete.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    next: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer { replay, next: 0 }
+    }
+
+    /// Played back every input and reached the end of the recording.
+    pub fn finished(&self, tick: u64) -> bool {
+        self.next >= self.replay.inputs.len() && tick >= self.replay.ticks
+    }
+}
+
+/// Records or plays back the actor's input, depending on whether the app
+/// inserted a `ReplayRecorder` or a `ReplayPlayer`.
+pub(crate) struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::GameSetup)
+                .label(TickStep::Input)
+                .with_system(replay_setup_system),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_update(GameState::Running)
+                .label(TickStep::Input)
+                .with_system(replay_play_system)
+                .with_system(replay_record_system.after(replay_play_system)),
+        )
+        .add_system_set_to_stage(
+            GameTick,
+            SystemSet::on_enter(GameState::LevelComplete)
+                .label(TickStep::Input)
+                .with_system(replay_save_system),
+        )
+        .add_system_to_stage(CoreStage::Last, replay_exit_system);
+    }
+}
+
+/// Starts a fresh recording, or rewinds the replay, for the level being set up.
+pub fn replay_setup_system(
+    level: CurrentLevel,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    player: Option<ResMut<ReplayPlayer>>,
+) {
+    let level = match level.get() {
+        Some(level) => level,
+        None => return,
+    };
+
+    if let Some(mut recorder) = recorder {
+        let path = recorder.path.take();
+        *recorder = ReplayRecorder::new(path, &level.id);
+    }
+    if let Some(mut player) = player {
+        if player.replay.level != level.id {
+            warn!(
+                "replay was recorded on \"{}\" but the level is \"{}\"",
+                player.replay.level, level.id
+            );
+        }
+        player.next = 0;
+    }
+}
+
+pub fn replay_play_system(
+    clock: Res<GameClock>,
+    player: Option<ResMut<ReplayPlayer>>,
+    mut input: ResMut<ActorInput>,
+) {
+    let mut player = match player {
+        Some(player) => player,
+        None => return,
+    };
+
+    while let Some((tick, recorded)) = player.replay.inputs.get(player.next) {
+        if *tick > clock.tick {
+            break;
+        }
+        *input = *recorded;
+        player.next += 1;
+    }
+}
+
+pub fn replay_record_system(
+    clock: Res<GameClock>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    input: Res<ActorInput>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    recorder.replay.ticks = clock.tick;
+    if *input != recorder.last {
+        recorder.last = *input;
+        recorder.replay.inputs.push((clock.tick, *input));
+    }
+}
+
+/// Saves the recording of the level just completed and stops recording.
+pub fn replay_save_system(mut commands: Commands, recorder: Option<Res<ReplayRecorder>>) {
+    if let Some(recorder) = recorder {
+        recorder.save();
+        commands.remove_resource::<ReplayRecorder>();
+    }
+}
+
+/// Saves the recording so far when the app is closed mid-level.
+pub fn replay_exit_system(
+    mut exit_events: EventReader<AppExit>,
+    recorder: Option<Res<ReplayRecorder>>,
+) {
+    if exit_events.iter().next().is_some() {
+        if let Some(recorder) = recorder {
+            recorder.save();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::*;
+use bevy::{prelude::*, utils::HashMap};
+use std::collections::VecDeque;
+
+type StandingSupportBlocks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static SpriteSize,
+        &'static Density,
+        Option<&'static Support>,
+    ),
+    (With<Block>, Without<BlockFalling>),
+>;
+
+/// `upper` sits on top of `lower`, with some overlap along the shared edge.
+fn rests_on(upper: Vec3, upper_size: Vec2, lower: Vec3, lower_size: Vec2) -> bool {
+    let gap = (upper - lower).truncate().abs() - (upper_size + lower_size) / 2.;
+    upper.y > lower.y && gap.y.abs() <= TOUCH_TOLERANCE && gap.x < -TOUCH_TOLERANCE
+}
+
+/// `a` and `b` are next to each other on the same level.
+fn side_by_side(a: Vec3, a_size: Vec2, b: Vec3, b_size: Vec2) -> bool {
+    let gap = (a - b).truncate().abs() - (a_size + b_size) / 2.;
+    gap.x.abs() <= TOUCH_TOLERANCE && gap.y < -TOUCH_TOLERANCE
+}
+
+/// Recomputes which standing blocks are held up whenever blocks have been
+/// removed. Blocks on the floor and support tiles are anchors, support tiles
+/// whether or not anything is left under them; support then spreads upwards
+/// to anything resting on a supported block, and sideways through adhesion,
+/// up to the level's adhesion limit measured from the nearest block that
+/// rests on something, with denser blocks counting for more of that
+/// distance. Whatever is left lost its support and starts falling.
+pub fn block_support_system(
+    mut commands: Commands,
+    block_grid: Res<BlockGrid>,
+    level: CurrentLevel,
+    unsupported_query: Query<&Unsupported>,
+    block_query: StandingSupportBlocks,
+) {
+    if unsupported_query.is_empty() {
+        return;
+    }
+    let level = match level.get() {
+        Some(level) => level,
+        None => return,
+    };
+    let floor = level.floor();
+    let adhesion_limit = level.adhesion.map(|cells| cells * GRID_WIDTH);
+
+    // horizontal distance to the block this one hangs off, zero when it rests on something
+    let mut adhesion: HashMap<Entity, f32> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for (entity, transform, sprite_size, _, support) in block_query.iter() {
+        let on_floor = transform.translation.y - sprite_size.0.y / 2. <= floor + TOUCH_TOLERANCE;
+        if support.is_some() || on_floor {
+            adhesion.insert(entity, 0.);
+            queue.push_back(entity);
+        }
+    }
+
+    while let Some(entity) = queue.pop_front() {
+        let distance = adhesion[&entity];
+        let (_, transform, sprite_size, _, _) = block_query.get(entity).unwrap();
+        let position = transform.translation;
+        let reach = sprite_size.0 + 2. * TOUCH_TOLERANCE;
+
+        for neighbour in block_grid.candidates(position, reach) {
+            let (_, neighbour_transform, neighbour_size, density, _) =
+                match block_query.get(neighbour) {
+                    Ok(block) => block,
+                    Err(_) => continue,
+                };
+            let neighbour_position = neighbour_transform.translation;
+
+            let neighbour_distance = if rests_on(
+                neighbour_position,
+                neighbour_size.0,
+                position,
+                sprite_size.0,
+            ) {
+                0.
+            } else if side_by_side(
+                neighbour_position,
+                neighbour_size.0,
+                position,
+                sprite_size.0,
+            ) {
+                distance + (neighbour_position.x - position.x).abs() * density.0
+            } else {
+                continue;
+            };
+
+            if adhesion_limit.is_some_and(|limit| neighbour_distance > limit) {
+                continue;
+            }
+            if adhesion
+                .get(&neighbour)
+                .is_none_or(|known| neighbour_distance < *known)
+            {
+                adhesion.insert(neighbour, neighbour_distance);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    for (entity, ..) in block_query.iter() {
+        if !adhesion.contains_key(&entity) {
+            commands.entity(entity).insert(BlockFalling);
+        }
+    }
+}
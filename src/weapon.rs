@@ -0,0 +1,298 @@
+use crate::*;
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashSet};
+use serde::Deserialize;
+
+pub const WEAPONS_FILE: &str = "actor.weapons.ron";
+
+/// How a weapon fires, and what its shots do.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum WeaponKind {
+    /// A shot per press, and a steady stream while fire is held.
+    Rapid,
+    /// Charges while fire is held and fires when it is let go. Held for
+    /// `seconds` or longer, the shot has `max_power` times the weapon's power.
+    Charge { seconds: f32, max_power: f32 },
+    /// `shots` shots at once, fanned out evenly over `angle` radians.
+    Spread { shots: u32, angle: f32 },
+    /// A shot that heats every block within `radius` pixels of where it lands.
+    Bomb { radius: f32 },
+    /// Heats the first block in its way for as long as fire is held.
+    Beam,
+}
+
+fn default_speed() -> f32 {
+    LASER_SPEED
+}
+
+fn default_power() -> f32 {
+    1.
+}
+
+/// One kind of weapon, as described in the weapons file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WeaponSpec {
+    pub name: String,
+    pub kind: WeaponKind,
+    /// Seconds after firing before it can fire again.
+    #[serde(default)]
+    pub cooldown: f32,
+    /// Shots it has for each level; unlimited without one. A beam uses one
+    /// each time it is switched on.
+    #[serde(default)]
+    pub ammo: Option<u32>,
+    pub sprite: String,
+    /// Length and thickness of each shot. A beam is as long as it reaches.
+    pub size: (f32, f32),
+    /// Pixels per second.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Power of each shot, in full shots, see `LaserPower`. A beam heats by
+    /// this many times `BEAM_HEAT` instead.
+    #[serde(default = "default_power")]
+    pub power: f32,
+}
+
+#[derive(Deserialize)]
+struct WeaponsFile {
+    weapons: Vec<WeaponSpec>,
+}
+
+/// Every weapon there is, in the order the actor switches through them.
+#[derive(TypeUuid)]
+#[uuid = "3e7b0d52-c1a8-4f96-8d27-6b9e4a05f1c3"]
+pub struct Arsenal {
+    weapons: Vec<WeaponSpec>,
+}
+
+impl RonAsset for Arsenal {
+    const EXTENSIONS: &'static [&'static str] = &["weapons.ron"];
+
+    fn from_ron(source: &str) -> Result<Self, DataError> {
+        let file: WeaponsFile = ron::from_str(source)?;
+        if file.weapons.is_empty() {
+            return Err(DataError {
+                line: 1,
+                column: 1,
+                message: "there are no weapons".to_string(),
+            });
+        }
+
+        let mut names = HashSet::new();
+        for weapon in file.weapons.iter() {
+            let defined_before = !names.insert(weapon.name.clone());
+            let problem = if defined_before {
+                Some("is defined twice")
+            } else if weapon.speed <= 0. || weapon.power <= 0. {
+                Some("needs a positive speed and power")
+            } else {
+                match weapon.kind {
+                    WeaponKind::Charge { seconds, max_power }
+                        if seconds <= 0. || max_power < 1. =>
+                    {
+                        Some("has to charge for some time, up to at least its own power")
+                    }
+                    WeaponKind::Spread { shots: 0, .. } => Some("has to fire at least one shot"),
+                    _ => None,
+                }
+            };
+            if let Some(problem) = problem {
+                return Err(DataError::at_name(
+                    source,
+                    &weapon.name,
+                    defined_before as usize,
+                    format!("weapon \"{}\" {}", weapon.name, problem),
+                ));
+            }
+        }
+
+        Ok(Arsenal {
+            weapons: file.weapons,
+        })
+    }
+}
+
+impl Arsenal {
+    pub fn get(&self, name: &str) -> Option<&WeaponSpec> {
+        self.weapons.iter().find(|weapon| weapon.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WeaponSpec> {
+        self.weapons.iter()
+    }
+}
+
+pub struct ArsenalHandle(pub Handle<Arsenal>);
+
+impl AssetHandle for ArsenalHandle {
+    type Asset = Arsenal;
+
+    fn handle(&self) -> &Handle<Arsenal> {
+        &self.0
+    }
+}
+
+/// The loaded arsenal, for systems that only need to read it.
+pub type Weapons<'w, 's> = Loaded<'w, 's, ArsenalHandle>;
+
+/// The weapons the actor carries this level, and the one in its hands.
+#[derive(Component, Clone, Debug)]
+pub struct Weapon {
+    /// Every weapon the level allows, in the order switching goes through them.
+    pub available: Vec<WeaponSpec>,
+    pub current: usize,
+    /// Shots left of each of `available`, `None` where they are unlimited.
+    pub ammo: Vec<Option<u32>>,
+    /// Seconds until each of `available` can fire again.
+    pub cooldown: Vec<f32>,
+    /// Seconds a charge weapon has been charging for.
+    pub charge: f32,
+}
+
+impl Weapon {
+    pub fn new(available: Vec<WeaponSpec>) -> Self {
+        Weapon {
+            ammo: available.iter().map(|weapon| weapon.ammo).collect(),
+            cooldown: vec![0.; available.len()],
+            available,
+            current: 0,
+            charge: 0.,
+        }
+    }
+
+    pub fn spec(&self) -> &WeaponSpec {
+        &self.available[self.current]
+    }
+
+    /// Cooled down and not out of ammo.
+    pub fn ready(&self) -> bool {
+        self.cooldown[self.current] <= 0. && self.ammo[self.current] != Some(0)
+    }
+
+    /// Takes up the next weapon, dropping any charge. Each weapon keeps
+    /// cooling down while it is put away.
+    pub fn switch(&mut self) {
+        self.current = (self.current + 1) % self.available.len();
+        self.charge = 0.;
+    }
+
+    /// Uses up a shot of the weapon in hand and starts it cooling down.
+    pub fn use_shot(&mut self) {
+        self.cooldown[self.current] = self.spec().cooldown;
+        if let Some(ammo) = &mut self.ammo[self.current] {
+            *ammo = ammo.saturating_sub(1);
+        }
+    }
+}
+
+/// Loads the weapons file. The actor's weapon systems run in `ActorPlugin`.
+pub(crate) struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Arsenal>()
+            .init_asset_loader::<RonLoader<Arsenal>>()
+            .add_system_set_to_stage(
+                GameTick,
+                SystemSet::on_enter(GameState::StartUp)
+                    .label(TickStep::Actor)
+                    .after(TickStep::Input)
+                    .with_system(arsenal_setup_system),
+            );
+    }
+}
+
+pub fn arsenal_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ArsenalHandle(asset_server.load(WEAPONS_FILE)));
+}
+
+pub fn actor_weapon_switch_system(
+    mut input: ResMut<ActorInput>,
+    mut query: Query<&mut Weapon, With<Actor>>,
+) {
+    if let Ok(mut weapon) = query.get_single_mut() {
+        if input.switch_weapon {
+            input.switch_weapon = false;
+            weapon.switch();
+        }
+    }
+}
+
+/// Fires the weapon in hand when it is ready, the way its kind fires. The
+/// beam fires from `laser_beam_system` instead.
+pub fn actor_weapon_fire_system(
+    mut commands: Commands,
+    clock: Res<GameClock>,
+    asset_server: Res<AssetServer>,
+    atlases: Res<AnimationAtlases>,
+    mut input: ResMut<ActorInput>,
+    mut level_stats: ResMut<LevelStats>,
+    mut query: Query<(&Transform, &Aim, &mut Weapon), With<Actor>>,
+) {
+    let (transform, aim, mut weapon) = match query.get_single_mut() {
+        Ok(actor) => actor,
+        Err(_) => return,
+    };
+    let delta = clock.delta_seconds();
+    for cooldown in weapon.cooldown.iter_mut() {
+        *cooldown = (*cooldown - delta).max(0.);
+    }
+    let pressed = std::mem::take(&mut input.fire);
+
+    let spec = weapon.spec().clone();
+    let power = match spec.kind {
+        WeaponKind::Beam => return,
+        WeaponKind::Rapid if !pressed && !input.firing => return,
+        WeaponKind::Charge { seconds, max_power } => {
+            if input.firing {
+                if weapon.ready() {
+                    weapon.charge += delta;
+                }
+                return;
+            }
+            if !pressed && weapon.charge <= 0. {
+                return;
+            }
+            // a tap fires straight away at the weapon's own power
+            let charged = (weapon.charge / seconds).min(1.);
+            weapon.charge = 0.;
+            spec.power * (1. + (max_power - 1.) * charged)
+        }
+        WeaponKind::Spread { .. } | WeaponKind::Bomb { .. } if !pressed => return,
+        _ => spec.power,
+    };
+    if !weapon.ready() {
+        return;
+    }
+    weapon.use_shot();
+    level_stats.shots += 1;
+
+    // the fire sheet is drawn pointing right, like the aim
+    let flash = Transform {
+        translation: transform.translation.truncate().extend(3.0),
+        rotation: Quat::from_rotation_z(aim.0),
+        ..Default::default()
+    };
+    spawn_effect(&mut commands, &atlases, ACTOR_FIRE_CLIP, flash);
+
+    let angles: Vec<f32> = match spec.kind {
+        WeaponKind::Spread { shots, angle } if shots > 1 => (0..shots)
+            .map(|n| aim.0 - angle / 2. + angle * n as f32 / (shots - 1) as f32)
+            .collect(),
+        _ => vec![aim.0],
+    };
+    for angle in angles {
+        let direction = Aim(angle).direction();
+        let start = transform.translation.truncate() + direction * ACTOR_SPRITE_SIZE.0;
+        let laser = spawn_laser(
+            &mut commands,
+            asset_server.load(spec.sprite.as_str()),
+            Vec2::from(spec.size),
+            start,
+            direction * spec.speed,
+            power,
+        );
+        if let WeaponKind::Bomb { radius } = spec.kind {
+            commands.entity(laser).insert(BlastRadius(radius));
+        }
+    }
+}
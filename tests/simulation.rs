@@ -0,0 +1,888 @@
+use bevy::{
+    ecs::event::{Events, ManualEventReader},
+    prelude::*,
+};
+use spritel::{harness::*, *};
+
+/// A 16x16 level with the actor bottom left, and `rows` as its bottom rows.
+fn level(rows: &[&str]) -> String {
+    let mut all_rows = vec!["................"; 16 - rows.len()];
+    all_rows.extend_from_slice(rows);
+    let rows: Vec<String> = all_rows.iter().map(|row| format!("\"{}\"", row)).collect();
+    format!(
+        "(name: \"test\", par_shots: 1, width: 16, height: 16, actor_start: (0, 15), rows: [{}])",
+        rows.join(", ")
+    )
+}
+
+/// Centre of a large block in the given column and row of a `level`.
+fn cell(x: usize, y: usize) -> Vec2 {
+    Vec2::new(
+        x as f32 * GRID_WIDTH - 512. + GRID_WIDTH / 2.,
+        512. - y as f32 * GRID_WIDTH - GRID_WIDTH / 2.,
+    )
+}
+
+#[test]
+fn laser_hit_heats_the_block_and_stops_the_laser() {
+    let mut harness = Harness::new(&level(&["....#..........."]));
+    harness.tap(0, KeyCode::Space);
+
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 1);
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+
+    let heat = harness.heat_of(cell(4, 15));
+    assert!(heat > 400., "block only got {} hotter", heat);
+    assert_eq!(harness.blocks().len(), 1);
+}
+
+#[test]
+fn decimate_carves_out_the_struck_side() {
+    let mut harness = Harness::new(&level(&["....#..........."]));
+    let block = cell(4, 15);
+    for tick in [0, 10, 20] {
+        harness.tap(tick, KeyCode::Space);
+    }
+
+    assert!(harness.step_until(120, |harness| {
+        harness
+            .blocks()
+            .iter()
+            .all(|block| block.material != "metal")
+    }));
+    harness.step(2);
+
+    let pieces = harness.blocks();
+    assert!(!pieces.is_empty() && pieces.len() < 16);
+    assert!(pieces.iter().all(|piece| piece.material == "metal_plate"));
+    assert!(pieces.iter().all(|piece| piece.side == BLOCK_MEDIUM_SIZE));
+
+    let column = |x: f32| {
+        pieces
+            .iter()
+            .filter(|piece| (piece.position.x - x).abs() < 0.01)
+            .count()
+    };
+    // the laser came in from the left, through the middle two rows
+    assert_eq!(column(block.x + 24.), 4);
+    assert_eq!(column(block.x - 24.), 2);
+}
+
+#[test]
+fn falling_block_lands_on_the_stack_below() {
+    let mut harness = Harness::new(&level(&[
+        "........#.......",
+        "................",
+        "................",
+        "................",
+        "................",
+        "........#.......",
+        "........#.......",
+    ]));
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+
+    harness.step(1);
+    assert_eq!(harness.count::<BlockFalling>(), 1);
+    assert!(harness.step_until(300, |harness| harness.count::<BlockFalling>() == 0));
+
+    let positions: Vec<Vec2> = harness
+        .blocks()
+        .iter()
+        .map(|block| block.position)
+        .collect();
+    assert_eq!(
+        positions,
+        vec![
+            cell(8, 15),
+            cell(8, 14),
+            cell(8, 15) + Vec2::new(0., 2. * GRID_WIDTH),
+        ]
+    );
+}
+
+#[test]
+fn falling_blocks_stop_speeding_up_and_land_once() {
+    // two blocks stacked at the top, with nothing under them
+    let mut rows = vec!["........#......."; 2];
+    rows.extend(["................"; 14]);
+    let mut harness = Harness::new(&level(&rows));
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+
+    let terminal_velocity = FallingConfig::default().terminal_velocity;
+    let mut landed_reader = ManualEventReader::<BlockLanded>::default();
+    let mut landings = Vec::new();
+    let mut top_speed: f32 = 0.;
+    for _ in 0..300 {
+        harness.step(1);
+        let world = harness.world();
+        let mut velocities = world.query_filtered::<&Velocity, With<Block>>();
+        for velocity in velocities.iter(world) {
+            top_speed = top_speed.max(-velocity.y);
+        }
+        let events = world.resource::<Events<BlockLanded>>();
+        landings.extend(
+            landed_reader
+                .iter(events)
+                .map(|landed| (landed.entity, landed.impact_speed)),
+        );
+    }
+
+    // long enough a drop to reach terminal velocity, but never to pass it
+    assert_eq!(top_speed, terminal_velocity);
+    assert_eq!(landings.len(), 2);
+    assert_ne!(landings[0].0, landings[1].0);
+    assert!(landings
+        .iter()
+        .all(|(_, speed)| *speed == terminal_velocity));
+
+    // snapped flat onto the floor, the block on top give or take rounding
+    let positions: Vec<Vec2> = harness
+        .blocks()
+        .iter()
+        .map(|block| block.position)
+        .collect();
+    assert_eq!(positions[0], cell(8, 15));
+    assert!(positions[1].distance(cell(8, 14)) < 0.01, "{:?}", positions);
+}
+
+#[test]
+fn cluster_falls_as_one_body_and_lands_in_shape() {
+    // an L of metal held up by the top of a glass pillar
+    let mut harness = Harness::new(&level(&[
+        "....##..........",
+        ".....#..........",
+        ".....G..........",
+        ".....G..........",
+        ".....G..........",
+        ".....G..........",
+    ]));
+    // bottom row first, like `blocks`
+    let start = [cell(5, 11), cell(4, 10), cell(5, 10)];
+    // how far each block of the L has come down
+    let drops = |harness: &mut Harness| -> Vec<f32> {
+        let blocks = harness.blocks().into_iter();
+        let metal = blocks.filter(|block| block.material == "metal");
+        metal
+            .zip(start)
+            .map(|(block, start)| start.y - block.position.y)
+            .collect()
+    };
+
+    assert_eq!(melt(&mut harness, |position, _| position == cell(5, 12)), 1);
+    harness.step(12);
+    assert_eq!(harness.count::<BlockFalling>(), 3);
+    let falling = drops(&mut harness);
+    assert!(falling[0] > 0.);
+    assert!(
+        falling.iter().all(|drop| (drop - falling[0]).abs() < 0.01),
+        "{:?}",
+        falling
+    );
+
+    // it lands a cell lower, on what is left of the pillar, without any
+    // block sliding sideways
+    assert!(harness.step_until(120, |harness| harness.count::<BlockFalling>() == 0));
+    let landed = drops(&mut harness);
+    assert!(
+        landed.iter().all(|drop| (drop - GRID_WIDTH).abs() < 0.01),
+        "{:?}",
+        landed
+    );
+    let columns: Vec<f32> = harness
+        .blocks()
+        .iter()
+        .map(|block| block.position.x)
+        .collect();
+    let (left, right) = (cell(4, 0).x, cell(5, 0).x);
+    assert_eq!(columns, vec![right, right, right, right, left, right]);
+}
+
+/// The game's materials plus "lead", as heavy as a support tile.
+fn materials_with_lead() -> String {
+    include_str!("../assets/blocks.materials.ron").replacen(
+        "    materials: [\n",
+        "    materials: [
+        (
+            name: \"lead\",
+            glyph: Some('L'),
+            sprite: \"base64/metalCenterSticker-64.png\",
+            tier: Large,
+            melting_point: 600.0,
+            heat_capacity: 1.0,
+            conductivity: 0.3,
+            density: 2.0,
+        ),\n",
+        1,
+    )
+}
+
+/// Two blocks reaching out sideways from a pillar, in a level where blocks
+/// hang at most two cells off whatever rests on something.
+fn cantilever(arm: &str) -> String {
+    level(&[&format!("....#{}.........", arm), "....#..........."])
+        .replace("rows:", "adhesion: Some(2.0), rows:")
+}
+
+#[test]
+fn cantilever_holds_light_blocks_further_out_than_heavy_ones() {
+    let mut harness = Harness::with_materials(&cantilever("##"), &materials_with_lead());
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+    harness.step(1);
+    assert_eq!(harness.count::<BlockFalling>(), 0);
+
+    // twice as dense, the outer block is four cells' worth out
+    let mut harness = Harness::with_materials(&cantilever("LL"), &materials_with_lead());
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+    harness.step(1);
+    assert_eq!(harness.count::<BlockFalling>(), 1);
+    assert!(harness.step_until(120, |harness| harness.count::<BlockFalling>() == 0));
+    let lead: Vec<Vec2> = harness
+        .blocks()
+        .into_iter()
+        .filter(|block| block.material == "lead")
+        .map(|block| block.position)
+        .collect();
+    assert_eq!(lead, vec![cell(6, 15), cell(5, 14)]);
+}
+
+#[test]
+fn support_tile_holds_up_blocks_with_nothing_under_it() {
+    // floating in mid air, with a block on top and another beside it
+    let floating = |tile: &str| {
+        level(&[
+            "........#.......",
+            &format!("........{}#......", tile),
+            "................",
+            "................",
+            "................",
+        ])
+    };
+
+    let mut harness = Harness::new(&floating("S"));
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+    harness.step(1);
+    assert_eq!(harness.count::<BlockFalling>(), 0);
+
+    let mut harness = Harness::new(&floating("#"));
+    harness.world().spawn().insert(Unsupported(Vec3::ZERO));
+    harness.step(1);
+    assert_eq!(harness.count::<BlockFalling>(), 3);
+}
+
+#[test]
+fn same_input_gives_identical_results() {
+    let run = || {
+        let mut harness = Harness::new(&level(&[
+            "....#...........",
+            "....#...#.......",
+            "....#...#.......",
+        ]));
+        for tick in [0, 4, 6, 30, 31, 32, 60] {
+            harness.tap(tick, KeyCode::Space);
+        }
+        harness.script(40, ScriptedKey::Press(KeyCode::Up));
+        harness.script(45, ScriptedKey::Release(KeyCode::Up));
+        harness.step(240);
+        harness.blocks()
+    };
+
+    let first = run();
+    assert!(first.len() > 3);
+    assert_eq!(first, run());
+}
+
+#[test]
+fn replay_reproduces_the_recorded_session() {
+    let mut recorded = Harness::load("levels/01.level.ron");
+    recorded.record();
+    for tick in [0, 20, 50] {
+        recorded.tap(tick, KeyCode::Space);
+    }
+    recorded.script(30, ScriptedKey::Press(KeyCode::Up));
+    recorded.script(40, ScriptedKey::Release(KeyCode::Up));
+    recorded.step(180);
+
+    let replay = Replay::from_ron(&recorded.recording().to_ron()).unwrap();
+    assert_eq!(replay.level, "levels/01.level.ron");
+    assert_eq!(replay.inputs.len(), 8);
+    let mut replayed = Harness::replay(replay);
+    assert!(replayed.step_until(200, |harness| harness.replay_finished()));
+
+    assert_eq!(replayed.blocks(), recorded.blocks());
+}
+
+/// The shipped bindings with `action` bound to `binding` alone.
+fn rebind(action: &str, binding: &str) -> ActionBindings {
+    let source = include_str!("../assets/input.bindings.ron");
+    let line = source
+        .lines()
+        .find(|line| line.trim_start().starts_with(&format!("{}:", action)))
+        .unwrap();
+    let rebound = format!("        {}: [{}],", action, binding);
+    ActionBindings::from_ron(&source.replace(line, &rebound)).unwrap()
+}
+
+#[test]
+fn rebound_key_fires_instead_of_the_default() {
+    let mut harness = Harness::new(&level(&["...............#"]));
+    harness.bind(rebind("Fire", "Key(F)"));
+
+    harness.tap(0, KeyCode::Space);
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 0);
+
+    harness.tap(2, KeyCode::F);
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 1);
+}
+
+#[test]
+fn rebound_gamepad_button_fires() {
+    let mut harness = Harness::new(&level(&["...............#"]));
+    harness.bind(rebind("Fire", "Button(East)"));
+    harness.gamepad(GamepadEventType::Connected);
+    harness.step(1);
+
+    harness.gamepad(GamepadEventType::ButtonChanged(
+        GamepadButtonType::South,
+        1.,
+    ));
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 0);
+
+    harness.gamepad(GamepadEventType::ButtonChanged(GamepadButtonType::East, 1.));
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 1);
+}
+
+#[test]
+fn rebound_gamepad_axis_moves_the_actor() {
+    let mut harness = Harness::new(&level(&["...............#"]));
+    harness.bind(rebind("MoveUp", "Axis(RightStickY, 1.0)"));
+    harness.gamepad(GamepadEventType::Connected);
+    harness.step(1);
+    let start = actor_position(&mut harness);
+
+    harness.gamepad(GamepadEventType::AxisChanged(
+        GamepadAxisType::LeftStickY,
+        1.,
+    ));
+    harness.step(10);
+    assert_eq!(actor_position(&mut harness), start);
+
+    harness.gamepad(GamepadEventType::AxisChanged(
+        GamepadAxisType::LeftStickY,
+        0.,
+    ));
+    harness.gamepad(GamepadEventType::AxisChanged(
+        GamepadAxisType::RightStickY,
+        1.,
+    ));
+    harness.step(10);
+    assert!(actor_position(&mut harness).y > start.y);
+}
+
+fn actor_position(harness: &mut Harness) -> Vec2 {
+    let mut query = harness.world().query_filtered::<&Transform, With<Actor>>();
+    query
+        .iter(harness.world())
+        .next()
+        .unwrap()
+        .translation
+        .truncate()
+}
+
+#[test]
+fn actor_stops_at_the_end_of_its_lane_and_against_blocks() {
+    let mut harness = Harness::new(&level(&["...............#"]));
+    harness.script(0, ScriptedKey::Press(KeyCode::Up));
+    harness.step(200);
+    assert_eq!(actor_position(&mut harness), cell(0, 0));
+
+    let mut harness = Harness::new(&level(&[
+        "#...............",
+        "................",
+        "................",
+    ]));
+    harness.script(0, ScriptedKey::Press(KeyCode::Up));
+    harness.step(60);
+    let below_block = cell(0, 13).y - GRID_WIDTH / 2. - ACTOR_HITBOX_SIZE / 2.;
+    assert!((actor_position(&mut harness).y - below_block).abs() <= 1.);
+}
+
+#[test]
+fn actor_carries_on_into_the_next_lane() {
+    let source = level(&["...............#"]).replace(
+        "rows:",
+        "lanes: [[(0, 15), (0, 8)], [(0, 8), (5, 8)], [(9, 0), (9, 15)]], rows:",
+    );
+    let mut harness = Harness::new(&source);
+    harness.script(0, ScriptedKey::Press(KeyCode::Down));
+    harness.step(200);
+    assert_eq!(actor_position(&mut harness), cell(5, 8));
+}
+
+#[test]
+fn turned_actor_fires_along_its_aim() {
+    // straight up and right from the actor at (0, 15)
+    let mut harness = Harness::new(&level(&[
+        "...#............",
+        "................",
+        "................",
+        "................",
+    ]));
+    // a quarter turn takes half a second, so this is 45 degrees
+    harness.script(0, ScriptedKey::Press(KeyCode::Left));
+    harness.script(15, ScriptedKey::Release(KeyCode::Left));
+    harness.tap(16, KeyCode::Space);
+
+    harness.step(18);
+    assert_eq!(harness.count::<Laser>(), 1);
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+
+    let block = harness.blocks()[0].position;
+    assert!(harness.heat_of(block) > 0., "the block was missed");
+}
+
+#[test]
+fn beam_heats_the_first_block_until_it_melts() {
+    let mut harness = Harness::new(&level(&["....#...#......."]));
+    harness.tap(0, KeyCode::Tab);
+    harness.script(2, ScriptedKey::Press(KeyCode::Space));
+
+    harness.step(4);
+    assert_eq!(harness.count::<Beam>(), 1);
+    assert_eq!(harness.count::<Laser>(), 0);
+    assert!(harness.step_until(120, |harness| {
+        harness
+            .blocks()
+            .iter()
+            .filter(|block| block.material == "metal")
+            .count()
+            == 1
+    }));
+    // the block behind was in the shadow of the first
+    let far_block = harness
+        .blocks()
+        .into_iter()
+        .find(|block| block.material == "metal");
+    assert_eq!(far_block.unwrap().position, cell(8, 15));
+
+    harness.script(harness.tick(), ScriptedKey::Release(KeyCode::Space));
+    harness.step(1);
+    assert_eq!(harness.count::<Beam>(), 0);
+}
+
+#[test]
+fn fast_laser_hits_only_the_first_thin_block_in_its_path() {
+    let source =
+        level(&["......p.p......."]).replace("rows:", "legend: {'p': \"metal_plate\"}, rows:");
+    let mut harness = Harness::new(&source);
+    // far enough per tick to pass over both plates at once
+    let start = cell(0, 15) + Vec2::new(GRID_WIDTH, 0.);
+    harness.fire(start, Vec2::new(30000., 0.), 1.);
+
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 0);
+    let blocks = harness.blocks();
+    assert!(blocks.iter().any(|block| block.material == "metal_shard"));
+    let plates: Vec<Vec2> = blocks
+        .iter()
+        .filter(|block| block.material == "metal_plate")
+        .map(|block| block.position)
+        .collect();
+    assert_eq!(plates, vec![cell(8, 15)]);
+}
+
+#[test]
+fn glass_passes_the_laser_on_with_less_power() {
+    let mut harness = Harness::new(&level(&["....G...#......."]));
+    harness.tap(0, KeyCode::Space);
+    harness.step(2);
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+
+    let (glass, metal) = (harness.heat_of(cell(4, 15)), harness.heat_of(cell(8, 15)));
+    assert!(glass > 0., "glass stayed cold");
+    // a full shot heats metal by 500
+    assert!(metal > 200. && metal < 300., "metal got {} hotter", metal);
+}
+
+#[test]
+fn powerful_laser_punches_through_plates_until_it_runs_out() {
+    let source =
+        level(&["....ppp.#......."]).replace("rows:", "legend: {'p': \"metal_plate\"}, rows:");
+    let mut harness = Harness::new(&source);
+    // each plate soaks up one shot's worth, so this gets half way into the third
+    let start = cell(1, 15);
+    harness.fire(start, Vec2::new(LASER_SPEED, 0.), 2.5);
+
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+    harness.step(2);
+    // the two it went through were decimated, and the third only warmed
+    let plates: Vec<f32> = harness
+        .blocks()
+        .iter()
+        .filter(|block| block.material == "metal_plate")
+        .map(|block| block.position.x)
+        .collect();
+    assert_eq!(plates, vec![cell(6, 15).x]);
+
+    // the metal block behind them never got hit
+    assert_eq!(harness.heat_of(cell(8, 15)), 0.);
+}
+
+#[test]
+fn laser_off_the_screen_hits_nothing() {
+    let mut harness = Harness::new(&level(&["...............#"]));
+    // its back end is past the right edge, but its front reaches the block
+    let block = cell(15, 15);
+    harness.fire(block + Vec2::new(GRID_WIDTH, 0.), Vec2::new(-60., 0.), 1.);
+
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 0);
+    assert_eq!(harness.heat_of(block), 0.);
+}
+
+/// A level with a block top right, out of the way of shots along the bottom
+/// row, and only `weapons` to fire.
+fn armed_level(weapons: &str) -> String {
+    let mut rows = vec!["...............#"];
+    rows.extend(["................"; 15]);
+    level(&rows).replace("rows:", &format!("weapons: [{}], rows:", weapons))
+}
+
+fn laser_powers(harness: &mut Harness) -> Vec<f32> {
+    let mut query = harness.world().query_filtered::<&LaserPower, With<Laser>>();
+    query.iter(harness.world()).map(|power| power.0).collect()
+}
+
+#[test]
+fn level_weapons_fire_in_turn_until_they_run_out() {
+    let mut harness = Harness::new(&armed_level("\"spread\", \"bomb\""));
+    harness.tap(0, KeyCode::Space);
+    harness.step(2);
+    assert_eq!(harness.count::<Laser>(), 3);
+
+    // three bombs, a second apart, and a fourth that has none left to fire
+    harness.tap(10, KeyCode::Tab);
+    for tick in [20, 85, 150, 215] {
+        harness.tap(tick, KeyCode::Space);
+    }
+    harness.step(240);
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 4);
+    let mut query = harness.world().query::<&Weapon>();
+    let weapon = query.iter(harness.world()).next().unwrap();
+    assert_eq!(weapon.spec().name, "bomb");
+    assert_eq!(weapon.ammo, vec![Some(19), Some(0)]);
+}
+
+#[test]
+fn switching_weapons_keeps_their_cooldowns() {
+    let mut harness = Harness::new(&armed_level("\"bomb\", \"spread\""));
+    harness.tap(0, KeyCode::Space);
+    harness.tap(2, KeyCode::Tab);
+    harness.tap(4, KeyCode::Tab);
+    harness.tap(6, KeyCode::Space);
+    harness.step(10);
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 1);
+
+    // the bomb is ready again a second after it fired
+    harness.tap(61, KeyCode::Space);
+    harness.step(55);
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 2);
+}
+
+#[test]
+fn charge_shot_grows_with_how_long_fire_is_held() {
+    let mut harness = Harness::new(&armed_level("\"charge\""));
+    harness.tap(0, KeyCode::Space);
+    harness.step(2);
+    let tapped = laser_powers(&mut harness);
+    assert_eq!(tapped.len(), 1);
+    assert!(tapped[0] < 1.1, "a tap fired at {}", tapped[0]);
+
+    // past the cooldown, and held for longer than a full charge
+    harness.script(40, ScriptedKey::Press(KeyCode::Space));
+    harness.script(140, ScriptedKey::Release(KeyCode::Space));
+    harness.step(138);
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 1);
+    harness.step(2);
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 2);
+    let charged = laser_powers(&mut harness).into_iter().fold(0., f32::max);
+    assert_eq!(charged, 4.);
+}
+
+#[test]
+fn held_rapid_fire_shoots_once_per_cooldown() {
+    let mut harness = Harness::new(&armed_level("\"laser\""));
+    harness.script(0, ScriptedKey::Press(KeyCode::Space));
+    harness.step(60);
+    // one straight away, then one each time the 0.15 second cooldown is
+    // over, which takes ten ticks
+    assert_eq!(harness.world().resource::<LevelStats>().shots, 6);
+}
+
+#[test]
+fn barrels_set_each_other_off_and_the_blast_fades_with_distance() {
+    let mut harness = Harness::new(&level(&["....BBB.#......."]));
+    harness.tap(0, KeyCode::Space);
+
+    assert!(harness.step_until(60, |harness| {
+        harness
+            .blocks()
+            .iter()
+            .all(|block| block.material != "barrel")
+    }));
+    harness.step(2);
+
+    // only the last barrel reached the metal, and not at full strength
+    let blocks = harness.blocks();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].position, cell(8, 15));
+    let heat = harness.heat_of(cell(8, 15));
+    assert!(heat > 500. && heat < 700., "metal got {} hotter", heat);
+}
+
+#[test]
+fn effects_play_their_clip_once_and_are_removed() {
+    let mut harness = Harness::new(&level(&["....#..........."]));
+    harness.tap(0, KeyCode::Space);
+    harness.step(2);
+    // the flash over the actor
+    assert_eq!(harness.count::<AnimatedEffect>(), 1);
+
+    assert!(harness.step_until(60, |harness| harness.count::<Explosion>() == 1));
+    let start = harness.tick();
+    assert!(harness.step_until(60, |harness| harness.count::<Explosion>() == 0));
+    // six frames at 20 fps
+    let ticks = harness.tick() - start;
+    assert!(
+        (18..=26).contains(&ticks),
+        "explosion lasted {} ticks",
+        ticks
+    );
+    assert_eq!(harness.count::<AnimatedEffect>(), 0);
+}
+
+/// Sets the block at `position` to `heat` degrees above ambient.
+fn warm(harness: &mut Harness, position: Vec2, heat: f32) {
+    let mut query = harness.world().query::<(&Transform, &mut BlockHeat)>();
+    for (transform, mut block_heat) in query.iter_mut(harness.world()) {
+        if transform.translation.truncate() == position {
+            block_heat.0 = AMBIENT_TEMPERATURE + heat;
+        }
+    }
+}
+
+#[test]
+fn heat_cools_towards_ambient_and_spreads_to_touching_blocks() {
+    // a block on its own and a pair touching side by side
+    let mut harness = Harness::new(&level(&["..#.....##......"]));
+    warm(&mut harness, cell(2, 15), 480.);
+    warm(&mut harness, cell(8, 15), 480.);
+
+    harness.step(60);
+    let alone = harness.heat_of(cell(2, 15));
+    let cooled = 480. * (1. - COOLING_RATE * TIMESTEP).powi(60);
+    assert!(
+        (alone - cooled).abs() < 1.,
+        "{} instead of {}",
+        alone,
+        cooled
+    );
+
+    // the neighbour takes some of the heat, so its hot side loses more
+    let hot = harness.heat_of(cell(8, 15));
+    let neighbour = harness.heat_of(cell(9, 15));
+    assert!(neighbour > 50., "neighbour only got {} hotter", neighbour);
+    assert!(hot < alone && hot > neighbour, "{} and {}", hot, neighbour);
+
+    // and in the end everything is back near ambient, without dropping below it
+    harness.step(2100);
+    for position in [cell(2, 15), cell(8, 15), cell(9, 15)] {
+        let heat = harness.heat_of(position);
+        assert!((0. ..1.).contains(&heat), "still {} above ambient", heat);
+    }
+}
+
+/// Heats every block `pick` chooses, by position and whether the level placed
+/// it, past its melting point.
+fn melt(harness: &mut Harness, pick: impl Fn(Vec2, bool) -> bool) -> usize {
+    let mut query = harness
+        .world()
+        .query::<(&Transform, &Thermal, &mut BlockHeat, Option<&LevelBlock>)>();
+    let mut melted = 0;
+    for (transform, thermal, mut heat, level_block) in query.iter_mut(harness.world()) {
+        if pick(transform.translation.truncate(), level_block.is_some()) {
+            heat.0 = thermal.melting_point * 2.;
+            melted += 1;
+        }
+    }
+    melted
+}
+
+#[test]
+fn only_the_levels_own_blocks_count_towards_destroying_blocks() {
+    let source = level(&["....#...#...#..."]).replace("rows:", "goal: DestroyBlocks(2), rows:");
+    let mut harness = Harness::new(&source);
+
+    assert_eq!(melt(&mut harness, |position, _| position == cell(4, 15)), 1);
+    harness.step(2);
+    // break every piece down as far as it goes
+    while melt(&mut harness, |_, level_block| !level_block) > 0 {
+        harness.step(2);
+    }
+    assert_eq!(harness.world().resource::<LevelStats>().blocks_destroyed, 1);
+    assert_eq!(harness.state(), GameState::Running);
+
+    melt(&mut harness, |position, _| position == cell(8, 15));
+    harness.step(2);
+    assert_eq!(harness.world().resource::<LevelStats>().blocks_destroyed, 2);
+    assert_ne!(harness.state(), GameState::Running);
+}
+
+/// Melts every block and piece until the level is complete.
+#[test]
+fn level_changed_on_disk_as_it_is_completed_reloads_first() {
+    let source = level(&["....#...#......."]).replace("rows:", "goal: DestroyBlocks(1), rows:");
+    let mut harness = Harness::new(&source);
+
+    // the same tick the block melts, the level file is saved again
+    assert_eq!(melt(&mut harness, |position, _| position == cell(4, 15)), 1);
+    let handle = harness.world().resource::<LevelHandle>().0.clone_weak();
+    harness
+        .world()
+        .resource_mut::<Events<AssetEvent<Level>>>()
+        .send(AssetEvent::Modified { handle });
+    // both want to leave the running state, but the reload gets there first
+    harness.step(1);
+    assert_ne!(harness.state(), GameState::Running);
+}
+
+fn clear_level(harness: &mut Harness) {
+    for _ in 0..50 {
+        if harness.state() != GameState::Running {
+            return;
+        }
+        melt(harness, |_, _| true);
+        harness.step(2);
+    }
+    panic!("level still running");
+}
+
+#[test]
+fn campaign_moves_on_to_the_next_level_and_then_completes() {
+    let mut harness = Harness::campaign(&["levels/01.level.ron", "levels/02.level.ron"]);
+    let playing = |harness: &mut Harness, level: usize| {
+        harness.step_until(10_000, |harness| {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            harness.state() == GameState::Running
+                && harness.world().resource::<CampaignProgress>().level == level
+        })
+    };
+
+    // "First Light" is cleared once every block and piece is gone
+    assert!(playing(&mut harness, 0));
+    assert!(harness.count::<Actor>() == 1 && harness.count::<LevelBlock>() > 0);
+    clear_level(&mut harness);
+
+    // "Pillars" needs six of its blocks destroyed
+    assert!(playing(&mut harness, 1));
+    assert_eq!(harness.world().resource::<LevelStats>().blocks_destroyed, 0);
+    let tops = |position| {
+        [5, 9, 13]
+            .iter()
+            .any(|&x| position == cell(x, 8) || position == cell(x, 9))
+    };
+    assert_eq!(melt(&mut harness, |position, _| tops(position)), 6);
+    harness.step(2);
+
+    assert_eq!(harness.state(), GameState::CampaignComplete);
+    assert_eq!(harness.world().resource::<CampaignProgress>().level, 2);
+    assert_eq!(harness.count::<Block>(), 0);
+    assert_eq!(harness.count::<Actor>(), 0);
+}
+
+#[test]
+fn replay_ends_the_campaign_with_the_replayed_level() {
+    let mut harness = Harness::campaign(&["levels/01.level.ron", "levels/02.level.ron"]);
+    harness.world().insert_resource(ReplayPlayer::new(Replay {
+        level: "levels/01.level.ron".to_string(),
+        ..Default::default()
+    }));
+    assert!(harness.step_until(10_000, |harness| {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        harness.state() == GameState::Running
+    }));
+
+    clear_level(&mut harness);
+
+    assert_eq!(harness.state(), GameState::CampaignComplete);
+    assert_eq!(harness.world().resource::<CampaignProgress>().level, 1);
+}
+
+#[test]
+fn recording_is_saved_when_its_level_is_complete() {
+    let path = std::env::temp_dir().join(format!("spritel-{}.replay.ron", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut harness = Harness::load("levels/01.level.ron");
+    harness.world().insert_resource(ReplayRecorder::new(
+        Some(path.clone()),
+        "levels/01.level.ron",
+    ));
+    harness.tap(0, KeyCode::Space);
+    harness.step(10);
+    assert!(!path.exists(), "saved before the level was complete");
+
+    clear_level(&mut harness);
+
+    let saved = Replay::from_ron(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.level, "levels/01.level.ron");
+    assert_eq!(saved.inputs.len(), 2);
+    assert!(harness.world().get_resource::<ReplayRecorder>().is_none());
+}
+
+#[test]
+fn mirror_reflects_the_laser_onto_another_block() {
+    let mut harness = Harness::new(&level(&[
+        "......#.........",
+        "......#.........",
+        "....M.#.........",
+    ]));
+    // down and to the right, onto the middle of the mirror's top
+    let start = cell(2, 13) + Vec2::new(GRID_WIDTH / 2., 0.);
+    harness.fire(start, Vec2::new(1., -1.).normalize() * LASER_SPEED, 1.);
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+
+    assert_eq!(harness.heat_of(cell(4, 15)), 0.);
+    let heat = harness.heat_of(cell(6, 13));
+    assert!(heat > 400., "reflected laser only heated by {}", heat);
+    assert!(harness.heat_of(cell(6, 14)) < heat / 10.);
+}
+
+#[test]
+fn splitter_sends_half_the_laser_back_and_half_on() {
+    let mut harness = Harness::new(&level(&["....Y...#......."]));
+    harness.tap(0, KeyCode::Space);
+    assert!(harness.step_until(30, |harness| harness.count::<Laser>() == 2));
+    assert!(harness.step_until(60, |harness| harness.count::<Laser>() == 0));
+
+    assert_eq!(harness.heat_of(cell(4, 15)), 0.);
+    let heat = harness.heat_of(cell(8, 15));
+    assert!(heat > 200. && heat < 300., "metal got {} hotter", heat);
+}
+
+#[test]
+fn beam_is_split_like_a_laser() {
+    let mut harness = Harness::new(&level(&["....Y...#......."]));
+    harness.tap(0, KeyCode::Tab);
+    harness.script(2, ScriptedKey::Press(KeyCode::Space));
+    harness.step(10);
+
+    // up to the splitter, back from it and on to the metal
+    assert_eq!(harness.count::<Beam>(), 3);
+    assert_eq!(harness.heat_of(cell(4, 15)), 0.);
+    assert!(harness.heat_of(cell(8, 15)) > 0.);
+}